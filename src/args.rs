@@ -0,0 +1,904 @@
+//! Parsing of the `#[contract(...)]` and `#[model(...)]` attribute arguments.
+//!
+//! Arguments are parsed with the Cairo parser by attaching them to a dummy function, and
+//! each argument is then normalized from one of the supported forms:
+//! `flag`, `key: value`, `key = value` or `key(a, b, ...)`.
+
+use cairo_lang_macro::Diagnostic;
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::attribute::structured::{AttributeArgVariant, AttributeStructurize};
+use cairo_lang_syntax::node::ast::{self, BinaryOperator, ModuleItem};
+use cairo_lang_syntax::node::TypedSyntaxNode;
+
+use crate::utils::{braces_balanced, is_name_valid, parses};
+
+/// Storage layout versions a model can be declared with, the current one last.
+const LAYOUT_VERSIONS: &[u64] = &[1];
+
+/// Widest indentation the generated code can be re-indented with.
+const MAX_INDENT: u64 = 8;
+
+/// Indentation of the generated code, the one of the patches.
+const DEFAULT_INDENT: usize = 4;
+
+/// Name of the contract state type generated by `#[starknet::contract]`.
+const DEFAULT_STATE_TYPE: &str = "ContractState";
+
+/// Method of the world provider component called by the default constructor.
+const DEFAULT_INITIALIZER: &str = "initializer";
+
+/// Value of a single attribute argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ArgValue {
+    /// `key`
+    Flag,
+    /// `key: value` or `key = value`
+    Value(String),
+    /// `key(a, b, ...)`
+    List(Vec<String>),
+}
+
+/// A single attribute argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Arg {
+    pub key: String,
+    pub value: ArgValue,
+}
+
+impl Arg {
+    /// A flag is either set by its sole presence, or with an explicit boolean value.
+    pub fn flag(&self) -> Result<bool, Diagnostic> {
+        match &self.value {
+            ArgValue::Flag => Ok(true),
+            ArgValue::Value(v) if v == "true" => Ok(true),
+            ArgValue::Value(v) if v == "false" => Ok(false),
+            _ => Err(self.invalid("a boolean")),
+        }
+    }
+
+    /// Content of a string literal argument.
+    pub fn string(&self) -> Result<String, Diagnostic> {
+        match &self.value {
+            ArgValue::Value(v) if v.len() >= 2 && v.starts_with('"') && v.ends_with('"') => Ok(v
+                [1..v.len() - 1]
+                .replace("\\\"", "\"")
+                .replace("\\\\", "\\")),
+            _ => Err(self.invalid("a string")),
+        }
+    }
+
+    pub fn int(&self) -> Result<u64, Diagnostic> {
+        match &self.value {
+            ArgValue::Value(v) => v.parse().map_err(|_| self.invalid("an integer")),
+            _ => Err(self.invalid("an integer")),
+        }
+    }
+
+    /// Items of a `key(a, b, ...)` argument.
+    pub fn list(&self, expected: &str) -> Result<Vec<String>, Diagnostic> {
+        match &self.value {
+            ArgValue::List(items) => Ok(items.clone()),
+            _ => Err(self.invalid(expected)),
+        }
+    }
+
+    pub fn unknown(&self, macro_name: &str) -> Diagnostic {
+        Diagnostic::error(format!("Unknown {} argument '{}'.", macro_name, self.key))
+    }
+
+    pub fn invalid(&self, expected: &str) -> Diagnostic {
+        Diagnostic::error(format!(
+            "Invalid value for argument '{}', expected {}.",
+            self.key, expected
+        ))
+    }
+}
+
+/// Paths the macros may be invoked with, the longest first.
+const MACRO_PATHS: &[&str] = &[
+    "dojo::contract",
+    "dojo::model",
+    "dojo::dojo_event",
+    "contract",
+    "model",
+    "dojo_event",
+];
+
+/// Strips the `#[...]` wrapper and macro path off a whole attribute, if given one.
+fn strip_macro_path(attr: &str) -> &str {
+    let attr = attr
+        .strip_prefix("#[")
+        .and_then(|a| a.strip_suffix(']'))
+        .map_or(attr, str::trim);
+
+    MACRO_PATHS
+        .iter()
+        .find_map(|path| {
+            attr.strip_prefix(path)
+                .map(str::trim_start)
+                .filter(|rest| rest.is_empty() || rest.starts_with('('))
+        })
+        .unwrap_or(attr)
+}
+
+/// Splits the raw attribute arguments into a list of [`Arg`].
+///
+/// The arguments may be given with or without their surrounding parentheses, and with or
+/// without the attribute path they were invoked with, as in `dojo::contract(...)`.
+pub(crate) fn parse_args(attr: &str) -> Result<Vec<Arg>, Vec<Diagnostic>> {
+    let attr = strip_macro_path(attr.trim());
+    if attr.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let args = if attr.starts_with('(') {
+        attr.to_string()
+    } else {
+        format!("({})", attr)
+    };
+
+    let db = SimpleParserDatabase::default();
+    let (root, diagnostics) =
+        db.parse_virtual_with_diagnostics(format!("#[args{}]\nfn __args() {{}}", args));
+
+    let invalid = || {
+        vec![Diagnostic::error(format!(
+            "Invalid macro arguments '{}'.",
+            attr
+        ))]
+    };
+
+    if diagnostics.check_error_free().is_err() {
+        return Err(invalid());
+    }
+
+    let Some(ModuleItem::FreeFunction(function)) = ast::SyntaxFile::from_syntax_node(&db, root)
+        .items(&db)
+        .elements(&db)
+        .into_iter()
+        .next()
+    else {
+        return Err(invalid());
+    };
+
+    let Some(attribute) = function.attributes(&db).elements(&db).into_iter().next() else {
+        return Err(invalid());
+    };
+
+    let mut parsed = vec![];
+    let mut errors = vec![];
+
+    for arg in attribute.structurize(&db).args {
+        let arg_text = arg.text(&db);
+
+        match arg.variant {
+            AttributeArgVariant::Named { value, name } => parsed.push(Arg {
+                key: name.text.to_string(),
+                value: ArgValue::Value(value.as_syntax_node().get_text_without_trivia(&db)),
+            }),
+            AttributeArgVariant::Unnamed(ast::Expr::Path(path)) => parsed.push(Arg {
+                key: path.as_syntax_node().get_text_without_trivia(&db),
+                value: ArgValue::Flag,
+            }),
+            AttributeArgVariant::Unnamed(ast::Expr::Binary(binary))
+                if matches!(binary.op(&db), BinaryOperator::Eq(_))
+                    && matches!(binary.lhs(&db), ast::Expr::Path(_)) =>
+            {
+                parsed.push(Arg {
+                    key: binary
+                        .lhs(&db)
+                        .as_syntax_node()
+                        .get_text_without_trivia(&db),
+                    value: ArgValue::Value(
+                        binary
+                            .rhs(&db)
+                            .as_syntax_node()
+                            .get_text_without_trivia(&db),
+                    ),
+                })
+            }
+            AttributeArgVariant::Unnamed(ast::Expr::FunctionCall(call)) => parsed.push(Arg {
+                key: call.path(&db).as_syntax_node().get_text_without_trivia(&db),
+                value: ArgValue::List(
+                    call.arguments(&db)
+                        .arguments(&db)
+                        .elements(&db)
+                        .iter()
+                        .map(|a| a.as_syntax_node().get_text_without_trivia(&db))
+                        .collect(),
+                ),
+            }),
+            _ => errors.push(Diagnostic::error(format!(
+                "Unsupported macro argument '{}'.",
+                arg_text
+            ))),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses the arguments of `macro_name` into `T`, one [`Arg`] at a time.
+///
+/// Duplicated arguments are rejected before reaching `apply`, which is expected to report
+/// unknown arguments with [`Arg::unknown`].
+fn parse_with<T: Default>(
+    attr: &str,
+    mut apply: impl FnMut(&mut T, &Arg) -> Result<(), Diagnostic>,
+) -> Result<T, Vec<Diagnostic>> {
+    let mut args = T::default();
+    let mut seen = vec![];
+    let mut diagnostics = vec![];
+
+    for arg in parse_args(attr)? {
+        if seen.contains(&arg.key) {
+            diagnostics.push(Diagnostic::error(format!(
+                "Duplicated macro argument '{}'.",
+                arg.key
+            )));
+            continue;
+        }
+        seen.push(arg.key.clone());
+
+        if let Err(diagnostic) = apply(&mut args, &arg) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(args)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Arguments of the `#[contract]` attribute.
+///
+/// Integrators building their own macro can reuse the argument grammar through
+/// [`ContractArgs::parse`], or build the arguments programmatically from the default ones,
+/// and expand a contract with them through [`expand_contract_with`](crate::expand_contract_with).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractArgs {
+    /// Generates a `world` function returning the world dispatcher.
+    pub world_helper: bool,
+    /// Generates a `world` function returning the world dispatcher stored in the user
+    /// `Storage` field of type `IWorldDispatcher`.
+    pub storage_world: bool,
+    /// Additional attributes applied to the generated module, such as `feature("x")`.
+    pub module_attrs: Vec<String>,
+    /// Version of the world interface the injected init entrypoint is exposed for. The world
+    /// is read through the same world provider component in every version.
+    pub world_version: WorldVersion,
+    /// Emits only the processed module body, without the `#[starknet::contract]` module
+    /// wrapper, so it can be composed elsewhere. Defaults are still injected in the body.
+    pub raw: bool,
+    /// Overrides the world provider method called by the constructor.
+    pub initializer: Option<String>,
+    /// Leaves the world provider initialization to the user constructor.
+    pub skip_initializer: bool,
+    /// Injects the upgradeable component, enabled by default.
+    pub upgradeable: bool,
+    /// Injects the world provider component, enabled by default.
+    pub world_provider: bool,
+    /// Imports the injected components, enabled by default. When disabled, the component paths
+    /// must be brought in scope by the user.
+    pub auto_import: bool,
+    /// Names of the `#[starknet::interface]` traits generated from the external functions.
+    /// A single interface gets all of them, several ones get the external functions of an
+    /// impl each, in source order, free functions being grouped together.
+    pub gen_interface: Vec<String>,
+    /// Silences the warning on contract names which are not in snake case.
+    pub allow_non_snake_case: bool,
+    /// Rejects contract names which don't fit in a short string, instead of warning.
+    pub strict_name_length: bool,
+    /// Generates a `#[cfg(test)]` impl setting the world dispatcher directly in storage.
+    pub test_world: bool,
+    /// Expression asserted by `dojo_init` once the caller is checked.
+    pub init_assert: Option<String>,
+    /// Leaves `dojo_init` callable by anyone, without checking the caller is the world.
+    pub open_init: bool,
+    /// Runs the init body at the end of the constructor initialization, forwarding it the
+    /// constructor parameters it takes. The caller check is left to the `dojo_init` entrypoint.
+    pub init_from_constructor: bool,
+    /// Checks made by `dojo_init` on its caller, see [`InitCheck`].
+    pub init_check: InitCheck,
+    /// Statements appended to the default constructor, after the initializer. Only valid when
+    /// the contract doesn't define its own constructor.
+    pub default_ctor_body: Option<String>,
+    /// Declares the trait of `dojo_init` explicitly, instead of through `#[generate_trait]`.
+    /// Implied by world versions embedding the init through an interface.
+    pub explicit_init_trait: bool,
+    /// Default namespace of the contract, used by the generated `world_default` helper.
+    pub namespace: Option<String>,
+    /// Overrides the name of the contract state type used by the injected items.
+    pub state_type: Option<String>,
+    /// Declares the components of user `#[substorage(v0)]` fields, along with their events.
+    pub auto_components: bool,
+    /// Impls embedded for the components of user `#[substorage(v0)]` fields, as pairs of the
+    /// component path, as written in the field type, and the name of its impl.
+    pub auto_component_impls: Vec<(String, String)>,
+    /// Warns about constructor and init bodies ending with a redundant `return;`.
+    pub warn_trailing_return: bool,
+    /// Binds `world` to the world dispatcher at the top of every external function.
+    pub inject_world: bool,
+    /// Derives `starknet::Store` on the local types used by the storage members, which are
+    /// otherwise only reported when missing it.
+    pub derive_store: bool,
+    /// Warns about entrypoints left unimplemented, such as `panic!("unimplemented")`.
+    pub report_todos: bool,
+    /// Warns about `use` items of the contract body whose names are never referenced.
+    pub warn_unused_imports: bool,
+    /// Generates `emit_*` helpers emitting the events of the components.
+    pub emit_helpers: bool,
+    /// Doesn't inject the `Event` enum, for contracts emitting no events, components included.
+    pub no_event: bool,
+    /// Applies `#[starknet::contract(account)]` to the generated module, for account contracts.
+    pub account: bool,
+    /// Predicate gating the whole generated module, as in `feature: "game"`. Unused with `raw`,
+    /// as there is no module to gate.
+    pub cfg: Option<String>,
+    /// Width of the indentation the generated code is re-indented with, by nesting depth,
+    /// 4 spaces by default.
+    pub indent: usize,
+}
+
+impl Default for ContractArgs {
+    fn default() -> Self {
+        Self {
+            world_helper: false,
+            storage_world: false,
+            module_attrs: vec![],
+            world_version: WorldVersion::default(),
+            raw: false,
+            initializer: None,
+            skip_initializer: false,
+            upgradeable: true,
+            world_provider: true,
+            auto_import: true,
+            gen_interface: vec![],
+            allow_non_snake_case: false,
+            strict_name_length: false,
+            test_world: false,
+            init_assert: None,
+            open_init: false,
+            init_from_constructor: false,
+            init_check: InitCheck::default(),
+            default_ctor_body: None,
+            explicit_init_trait: false,
+            namespace: None,
+            state_type: None,
+            auto_components: false,
+            auto_component_impls: vec![],
+            report_todos: false,
+            warn_unused_imports: false,
+            warn_trailing_return: false,
+            inject_world: false,
+            derive_store: false,
+            no_event: false,
+            emit_helpers: false,
+            account: false,
+            cfg: None,
+            indent: DEFAULT_INDENT,
+        }
+    }
+}
+
+impl ContractArgs {
+    /// Parses the raw arguments of the attribute, with or without their parentheses.
+    pub fn parse(attr: &str) -> Result<Self, Vec<Diagnostic>> {
+        let args = parse_with(attr, |args: &mut Self, arg| match arg.key.as_str() {
+            "world_helper" => arg.flag().map(|v| args.world_helper = v),
+            "storage_world" => arg.flag().map(|v| args.storage_world = v),
+            "module_attrs" => {
+                let attrs = arg.list("a list of attributes")?;
+                if let Some(attr) = attrs
+                    .iter()
+                    .find(|a| !parses(&format!("#[{}]\nmod m {{}}", a)))
+                {
+                    return Err(Diagnostic::error(format!(
+                        "Invalid module attribute '{}' in 'module_attrs'.",
+                        attr
+                    )));
+                }
+                args.module_attrs = attrs;
+                Ok(())
+            }
+            "raw" => arg.flag().map(|v| args.raw = v),
+            "upgradeable" => arg.flag().map(|v| args.upgradeable = v),
+            "world_provider" => arg.flag().map(|v| args.world_provider = v),
+            "auto_import" => arg.flag().map(|v| args.auto_import = v),
+            "test_world" => arg.flag().map(|v| args.test_world = v),
+            "skip_initializer" => arg.flag().map(|v| args.skip_initializer = v),
+            "auto_components" => {
+                let ArgValue::List(_) = arg.value else {
+                    return arg.flag().map(|v| args.auto_components = v);
+                };
+                for embed in arg.list("a list of component impls")? {
+                    let Some((path, name)) = embed
+                        .split_once('=')
+                        .map(|(path, name)| (path.trim(), name.trim()))
+                        .filter(|(path, name)| {
+                            !path.is_empty() && !name.is_empty() && is_name_valid(name)
+                        })
+                    else {
+                        return Err(Diagnostic::error(format!(
+                            "Invalid component impl '{}' in 'auto_components', expected `path::to::Component = ImplName`.",
+                            embed
+                        )));
+                    };
+                    args.auto_component_impls
+                        .push((path.to_string(), name.to_string()));
+                }
+                args.auto_components = true;
+                Ok(())
+            }
+            "account" => arg.flag().map(|v| args.account = v),
+            "no_event" => arg.flag().map(|v| args.no_event = v),
+            "emit_helpers" => arg.flag().map(|v| args.emit_helpers = v),
+            "report_todos" => arg.flag().map(|v| args.report_todos = v),
+            "warn_unused_imports" => arg.flag().map(|v| args.warn_unused_imports = v),
+            "warn_trailing_return" => arg.flag().map(|v| args.warn_trailing_return = v),
+            "inject_world" => arg.flag().map(|v| args.inject_world = v),
+            "derive_store" => arg.flag().map(|v| args.derive_store = v),
+            "open_init" => arg.flag().map(|v| args.open_init = v),
+            "init_from_constructor" => arg.flag().map(|v| args.init_from_constructor = v),
+            "explicit_init_trait" => arg.flag().map(|v| args.explicit_init_trait = v),
+            "allow_non_snake_case" => arg.flag().map(|v| args.allow_non_snake_case = v),
+            "strict_name_length" => arg.flag().map(|v| args.strict_name_length = v),
+            "initializer" => {
+                let initializer = arg.string()?;
+                if initializer.is_empty() || !is_name_valid(&initializer) {
+                    return Err(Diagnostic::error(format!(
+                        "The initializer name '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+                        initializer
+                    )));
+                }
+                args.initializer = Some(initializer);
+                Ok(())
+            }
+            "gen_interface" => {
+                let interfaces = match arg.value {
+                    ArgValue::List(_) => arg.list("a list of interface names")?,
+                    _ => vec![arg.string()?],
+                };
+                for (i, interface) in interfaces.iter().enumerate() {
+                    if interface.is_empty() || !is_name_valid(interface) {
+                        return Err(Diagnostic::error(format!(
+                            "The interface name '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+                            interface
+                        )));
+                    }
+                    if interfaces[..i].contains(interface) {
+                        return Err(Diagnostic::error(format!(
+                            "The interface '{}' is listed more than once in 'gen_interface'.",
+                            interface
+                        )));
+                    }
+                }
+                args.gen_interface = interfaces;
+                Ok(())
+            }
+            "init_assert" => {
+                let expr = arg.string()?;
+                // A constant initializer only accepts a single expression.
+                if expr.trim().is_empty() || !parses(&format!("const C: bool = {};", expr)) {
+                    return Err(Diagnostic::error(format!(
+                        "Invalid expression '{}' in 'init_assert'.",
+                        expr
+                    )));
+                }
+                args.init_assert = Some(expr);
+                Ok(())
+            }
+            "default_ctor_body" => {
+                let body = arg.string()?;
+                if body.trim().is_empty()
+                    || !braces_balanced(&body)
+                    || !parses(&format!("fn f() {{\n{}\n}}", body))
+                {
+                    return Err(Diagnostic::error(format!(
+                        "Invalid statements '{}' in 'default_ctor_body'.",
+                        body
+                    )));
+                }
+                args.default_ctor_body = Some(body);
+                Ok(())
+            }
+            "cfg" => {
+                let predicate = arg.string()?;
+                if predicate.trim().is_empty()
+                    || !parses(&format!("#[cfg({})]\nmod m {{}}", predicate))
+                {
+                    return Err(Diagnostic::error(format!(
+                        "Invalid predicate '{}' in 'cfg'.",
+                        predicate
+                    )));
+                }
+                args.cfg = Some(predicate);
+                Ok(())
+            }
+            "namespace" => {
+                let namespace = arg.string()?;
+                if namespace.is_empty() || !is_name_valid(&namespace) {
+                    return Err(Diagnostic::error(format!(
+                        "The namespace '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+                        namespace
+                    )));
+                }
+                args.namespace = Some(namespace);
+                Ok(())
+            }
+            "state_type" => {
+                let state_type = arg.string()?;
+                if state_type.is_empty() || !is_name_valid(&state_type) {
+                    return Err(Diagnostic::error(format!(
+                        "The state type name '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+                        state_type
+                    )));
+                }
+                args.state_type = Some(state_type);
+                Ok(())
+            }
+            "indent" => {
+                let width = arg.int()?;
+                if !(1..=MAX_INDENT).contains(&width) {
+                    return Err(Diagnostic::error(format!(
+                        "Unsupported indent width '{}', expected between 1 and {}.",
+                        width, MAX_INDENT
+                    )));
+                }
+                args.indent = width as usize;
+                Ok(())
+            }
+            "init_check" => {
+                let check = arg.string()?;
+                args.init_check = InitCheck::from_name(&check).ok_or_else(|| {
+                    Diagnostic::error(format!(
+                        "Unsupported init check '{}', supported checks are: {}.",
+                        check,
+                        InitCheck::ALL.map(|c| c.name()).join(", ")
+                    ))
+                })?;
+                Ok(())
+            }
+            "world_version" => {
+                let version = arg.int()?;
+                args.world_version = WorldVersion::from_number(version).ok_or_else(|| {
+                    Diagnostic::error(format!(
+                        "Unsupported world version '{}', supported versions are: {}.",
+                        version,
+                        WorldVersion::ALL.map(|v| v.number().to_string()).join(", ")
+                    ))
+                })?;
+                Ok(())
+            }
+            _ => Err(arg.unknown("contract")),
+        })?;
+
+        if args.world_helper && args.storage_world {
+            return Err(vec![Diagnostic::error(
+                "The 'world_helper' and 'storage_world' arguments both generate a 'world' function, only one can be set.",
+            )]);
+        }
+
+        // The world is only known through the world provider component.
+        if !args.world_provider && !args.open_init {
+            return Err(vec![Diagnostic::error(
+                "The 'dojo_init' caller check requires the world provider, which is disabled by `world_provider = false`: set 'open_init' to leave 'dojo_init' unchecked.",
+            )]);
+        }
+
+        if !args.world_provider && args.world_helper {
+            return Err(vec![Diagnostic::error(
+                "The 'world_helper' argument reads the world from the world provider, which is disabled by `world_provider = false`.",
+            )]);
+        }
+
+        if !args.world_provider && args.test_world {
+            return Err(vec![Diagnostic::error(
+                "The 'test_world' argument writes the world to the world provider storage, which is disabled by `world_provider = false`.",
+            )]);
+        }
+
+        if !args.world_provider && args.inject_world {
+            return Err(vec![Diagnostic::error(
+                "The 'inject_world' argument reads the world from the world provider, which is disabled by `world_provider = false`.",
+            )]);
+        }
+
+        Ok(args)
+    }
+
+    /// Method of the world provider component called by the constructor.
+    pub fn initializer(&self) -> &str {
+        self.initializer.as_deref().unwrap_or(DEFAULT_INITIALIZER)
+    }
+
+    /// Name of the contract state type, `self` of every injected function.
+    pub fn state_type(&self) -> &str {
+        self.state_type.as_deref().unwrap_or(DEFAULT_STATE_TYPE)
+    }
+}
+
+/// Version of the world a contract is built against, which selects how its init entrypoint
+/// is exposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WorldVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl WorldVersion {
+    pub const ALL: [WorldVersion; 2] = [WorldVersion::V1, WorldVersion::V2];
+
+    pub fn number(&self) -> u64 {
+        match self {
+            WorldVersion::V1 => 1,
+            WorldVersion::V2 => 2,
+        }
+    }
+
+    pub fn from_number(number: u64) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.number() == number)
+    }
+
+    /// Whether the init entrypoint is embedded with `#[abi(embed_v0)]`, rather than exposed
+    /// as a per-item `#[external(v0)]` function.
+    pub fn embeds_init(&self) -> bool {
+        match self {
+            WorldVersion::V1 => false,
+            WorldVersion::V2 => true,
+        }
+    }
+}
+
+/// Check made by `dojo_init` to only be callable by the world.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InitCheck {
+    /// The direct caller of the entrypoint must be the world.
+    #[default]
+    Caller,
+    /// The account the transaction originates from must be the world.
+    Origin,
+}
+
+impl InitCheck {
+    pub const ALL: [InitCheck; 2] = [InitCheck::Caller, InitCheck::Origin];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InitCheck::Caller => "caller",
+            InitCheck::Origin => "origin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// Arguments of the `#[model]` attribute.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ModelArgs {
+    /// Skips the `*Value` struct and its impls, the model being read and written as a whole.
+    pub no_value_struct: bool,
+    /// Computes the model selector with the legacy, namespace-less, scheme.
+    pub legacy: bool,
+    /// Additional traits derived on the model and value structs, such as `PartialEq`.
+    pub derive: Vec<String>,
+    /// Storage layout version of the model, the current one by default.
+    pub layout_version: Option<u64>,
+    /// Derives `Default` on the value struct.
+    pub default: bool,
+    /// Warns about value members of user types, which must implement `Introspect`.
+    pub nested_hints: bool,
+}
+
+impl ModelArgs {
+    pub fn parse(attr: &str) -> Result<Self, Vec<Diagnostic>> {
+        let args = parse_with(attr, |args: &mut Self, arg| match arg.key.as_str() {
+            "no_value_struct" => arg.flag().map(|v| args.no_value_struct = v),
+            "legacy" => arg.flag().map(|v| args.legacy = v),
+            "default" => arg.flag().map(|v| args.default = v),
+            "nested_hints" => arg.flag().map(|v| args.nested_hints = v),
+            "layout_version" => {
+                let version = arg.int()?;
+                if !LAYOUT_VERSIONS.contains(&version) {
+                    return Err(Diagnostic::error(format!(
+                        "Unsupported layout version '{}', supported versions are: {}.",
+                        version,
+                        LAYOUT_VERSIONS
+                            .iter()
+                            .map(|v| v.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )));
+                }
+                args.layout_version = Some(version);
+                Ok(())
+            }
+            "derive" => {
+                let derives = arg.list("a list of traits")?;
+                if let Some(derive) = derives
+                    .iter()
+                    .find(|d| !parses(&format!("#[derive({})]\nstruct S {{}}", d)))
+                {
+                    return Err(Diagnostic::error(format!(
+                        "Invalid trait '{}' in 'derive'.",
+                        derive
+                    )));
+                }
+                args.derive = derives;
+                Ok(())
+            }
+            _ => Err(arg.unknown("model")),
+        })?;
+
+        if args.default && args.no_value_struct {
+            return Err(vec![Diagnostic::error(
+                "The 'default' argument derives Default on the value struct, which is skipped by 'no_value_struct'.",
+            )]);
+        }
+
+        Ok(args)
+    }
+
+    pub fn layout_version(&self) -> u64 {
+        self.layout_version
+            .unwrap_or(LAYOUT_VERSIONS[LAYOUT_VERSIONS.len() - 1])
+    }
+}
+
+/// Arguments of the `#[dojo_event]` attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EventArgs {
+    /// Keeps every emitted event, instead of only the latest one per keys. Enabled by default.
+    pub historical: bool,
+}
+
+impl Default for EventArgs {
+    fn default() -> Self {
+        Self { historical: true }
+    }
+}
+
+impl EventArgs {
+    pub fn parse(attr: &str) -> Result<Self, Vec<Diagnostic>> {
+        parse_with(attr, |args: &mut Self, arg| match arg.key.as_str() {
+            "historical" => arg.flag().map(|v| args.historical = v),
+            _ => Err(arg.unknown("dojo_event")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(attr: &str) -> Vec<String> {
+        ContractArgs::parse(attr)
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn contract_args_are_parsed() {
+        let args = ContractArgs::parse(
+            "(namespace: \"game\", world_helper, upgradeable: false, world_version: 2)",
+        )
+        .unwrap();
+
+        assert_eq!(
+            args,
+            ContractArgs {
+                namespace: Some("game".to_string()),
+                world_helper: true,
+                upgradeable: false,
+                world_version: WorldVersion::V2,
+                ..ContractArgs::default()
+            }
+        );
+        assert_eq!(ContractArgs::parse("").unwrap(), ContractArgs::default());
+        assert_eq!(
+            ContractArgs::parse("#[dojo::contract(world_helper)]").unwrap(),
+            ContractArgs::parse("world_helper").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_contract_args_are_reported() {
+        assert_eq!(errors("colour"), ["Unknown contract argument 'colour'."]);
+        assert_eq!(errors("raw, raw"), ["Duplicated macro argument 'raw'."]);
+        assert_eq!(
+            errors("raw: 1"),
+            ["Invalid value for argument 'raw', expected a boolean."]
+        );
+        assert_eq!(
+            errors("namespace: game"),
+            ["Invalid value for argument 'namespace', expected a string."]
+        );
+        assert_eq!(
+            errors("namespace: \"my-game\""),
+            ["The namespace 'my-game' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_)."]
+        );
+        assert_eq!(
+            errors("world_version: 9"),
+            ["Unsupported world version '9', supported versions are: 1, 2."]
+        );
+        assert_eq!(
+            errors("world_helper, storage_world"),
+            ["The 'world_helper' and 'storage_world' arguments both generate a 'world' function, only one can be set."]
+        );
+    }
+
+    #[test]
+    fn every_invalid_arg_is_reported() {
+        assert_eq!(
+            errors("colour, raw: 1"),
+            [
+                "Unknown contract argument 'colour'.",
+                "Invalid value for argument 'raw', expected a boolean."
+            ]
+        );
+    }
+
+    #[test]
+    fn checked_init_requires_the_world_provider() {
+        assert_eq!(
+            errors("world_provider: false"),
+            ["The 'dojo_init' caller check requires the world provider, which is disabled by `world_provider = false`: set 'open_init' to leave 'dojo_init' unchecked."]
+        );
+        assert!(ContractArgs::parse("world_provider: false, open_init").is_ok());
+    }
+
+    #[test]
+    fn multi_line_args_with_a_trailing_comma_are_parsed() {
+        let args = ContractArgs::parse(
+            "(
+                namespace: \"x\",
+                upgradeable: false,
+            )",
+        )
+        .unwrap();
+
+        assert_eq!(
+            args,
+            ContractArgs {
+                namespace: Some("x".to_string()),
+                upgradeable: false,
+                ..ContractArgs::default()
+            }
+        );
+    }
+
+    #[test]
+    fn auto_components_map_component_paths_to_impls() {
+        let args =
+            ContractArgs::parse("auto_components(a::BComponent = BImpl, CComponent = CImpl)")
+                .unwrap();
+        assert!(args.auto_components);
+        assert_eq!(
+            args.auto_component_impls,
+            [
+                ("a::BComponent".to_string(), "BImpl".to_string()),
+                ("CComponent".to_string(), "CImpl".to_string())
+            ]
+        );
+        assert!(ContractArgs::parse("auto_components")
+            .unwrap()
+            .auto_component_impls
+            .is_empty());
+
+        assert_eq!(
+            errors("auto_components(a::BComponent)"),
+            ["Invalid component impl 'a::BComponent' in 'auto_components', expected `path::to::Component = ImplName`."]
+        );
+    }
+}
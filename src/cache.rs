@@ -0,0 +1,153 @@
+//! Cache of the expansions run by the build, so unchanged items are not expanded again.
+//!
+//! Entries are keyed by the whole text of what the expansion depends on, the attribute
+//! arguments and the item included: changing any argument, such as the namespace, misses.
+//! Edited items leave their previous expansions behind, so the least recently used entries
+//! are evicted past [`CAPACITY`].
+
+use std::collections::HashMap;
+#[cfg(feature = "cache-stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use cairo_lang_macro::ProcMacroResult;
+
+/// Number of expansions kept, well above the number of items of a package.
+const CAPACITY: usize = 1024;
+
+static CACHE: OnceLock<Mutex<Lru>> = OnceLock::new();
+
+#[cfg(feature = "cache-stats")]
+static HITS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of expansions served from the cache.
+#[cfg(feature = "cache-stats")]
+pub fn cache_hits() -> usize {
+    HITS.load(Ordering::Relaxed)
+}
+
+/// Returns the cached result of the expansion keyed by `key`, or runs and caches it.
+pub(crate) fn cached(key: &[&str], expand: impl FnOnce() -> ProcMacroResult) -> ProcMacroResult {
+    let key = key.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+    let cache = CACHE.get_or_init(|| Mutex::new(Lru::new(CAPACITY)));
+
+    if let Some(result) = cache.lock().ok().and_then(|mut c| c.get(&key)) {
+        #[cfg(feature = "cache-stats")]
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return result;
+    }
+
+    let result = expand();
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(key, copy(&result));
+    }
+    result
+}
+
+/// Expansions along with the tick they were last used at.
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<Vec<String>, (u64, ProcMacroResult)>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &[String]) -> Option<ProcMacroResult> {
+        self.tick += 1;
+        let (used, result) = self.entries.get_mut(key)?;
+        *used = self.tick;
+        Some(copy(result))
+    }
+
+    fn insert(&mut self, key: Vec<String>, result: ProcMacroResult) {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(key, (self.tick, result));
+    }
+}
+
+/// `ProcMacroResult` isn't `Clone`, but all its fields are.
+fn copy(result: &ProcMacroResult) -> ProcMacroResult {
+    ProcMacroResult {
+        token_stream: result.token_stream.clone(),
+        aux_data: result.aux_data.clone(),
+        diagnostics: result.diagnostics.clone(),
+        full_path_markers: result.full_path_markers.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cairo_lang_macro::TokenStream;
+
+    fn result(code: &str) -> ProcMacroResult {
+        ProcMacroResult::new(TokenStream::new(code.to_string()))
+    }
+
+    fn key(name: &str) -> Vec<String> {
+        vec![name.to_string()]
+    }
+
+    #[test]
+    fn identical_inputs_hit_the_cache() {
+        let key = ["test", "namespace: \"game\"", "mod identical_inputs {}"];
+        let mut runs = 0;
+
+        #[cfg(feature = "cache-stats")]
+        let hits = cache_hits();
+
+        for _ in 0..2 {
+            let expansion = cached(&key, || {
+                runs += 1;
+                result("mod identical_inputs {}")
+            });
+            assert_eq!(
+                expansion.token_stream.to_string(),
+                "mod identical_inputs {}"
+            );
+        }
+        assert_eq!(runs, 1);
+
+        #[cfg(feature = "cache-stats")]
+        assert!(cache_hits() > hits);
+
+        cached(&["test", "namespace: \"other\"", key[2]], || {
+            runs += 1;
+            result("mod identical_inputs {}")
+        });
+        assert_eq!(runs, 2);
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut lru = Lru::new(2);
+        lru.insert(key("a"), result("a"));
+        lru.insert(key("b"), result("b"));
+        assert!(lru.get(&key("a")).is_some());
+
+        lru.insert(key("c"), result("c"));
+        assert_eq!(lru.entries.len(), 2);
+        assert!(lru.get(&key("b")).is_none());
+        assert!(lru.get(&key("a")).is_some());
+        assert!(lru.get(&key("c")).is_some());
+    }
+}
@@ -0,0 +1,153 @@
+//! Components injected by the `contract` macro, along with their storage and events.
+
+use crate::args::ContractArgs;
+
+/// A component injected in every contract, unless disabled through its argument.
+#[derive(Debug)]
+pub(crate) struct Component {
+    /// Argument of the `contract` macro disabling the component, as in `upgradeable = false`.
+    pub arg: &'static str,
+    /// `use` path bringing the component in scope.
+    pub import: &'static str,
+    /// Component module name, as used in `component!(path: ...)`.
+    pub path: &'static str,
+    pub storage: &'static str,
+    pub event: &'static str,
+    /// Name of the embedded impl, and the component impl it is bound to, without its state.
+    pub embed: (&'static str, &'static str),
+    enabled: fn(&ContractArgs) -> bool,
+}
+
+pub(crate) static COMPONENTS: [Component; 2] = [
+    Component {
+        arg: "upgradeable",
+        import: "dojo::contract::components::upgradeable::upgradeable_cpt",
+        path: "upgradeable_cpt",
+        storage: "upgradeable",
+        event: "UpgradeableEvent",
+        embed: ("UpgradeableImpl", "upgradeable_cpt::UpgradeableImpl"),
+        enabled: |args| args.upgradeable,
+    },
+    Component {
+        arg: "world_provider",
+        import: "dojo::contract::components::world_provider::{world_provider_cpt, world_provider_cpt::InternalTrait as WorldProviderInternal, IWorldProvider}",
+        path: "world_provider_cpt",
+        storage: "world_provider",
+        event: "WorldProviderEvent",
+        embed: ("WorldProviderImpl", "world_provider_cpt::WorldProviderImpl"),
+        enabled: |args| args.world_provider,
+    },
+];
+
+impl Component {
+    pub fn is_enabled(&self, args: &ContractArgs) -> bool {
+        (self.enabled)(args)
+    }
+
+    pub fn event_type(&self) -> String {
+        format!("{}::Event", self.path)
+    }
+
+    /// Whether the given type refers to an item of the component module.
+    pub fn is_referenced_by(&self, ty: &str) -> bool {
+        ty == self.path || ty.starts_with(&format!("{}::", self.path))
+    }
+
+    /// Import, if requested, declaration and embedded impl of the component in the contract
+    /// module.
+    pub fn declaration(&self, state_type: &str, import: bool) -> String {
+        let import = if import {
+            format!("use {};\n    ", self.import)
+        } else {
+            String::new()
+        };
+
+        format!(
+            "{}component!(path: {}, storage: {}, event: {});
+
+    #[abi(embed_v0)]
+    impl {} = {}<{}>;
+",
+            import, self.path, self.storage, self.event, self.embed.0, self.embed.1, state_type
+        )
+    }
+
+    pub fn event_variant(&self) -> String {
+        format!("{}: {},", self.event, self.event_type())
+    }
+
+    pub fn storage_member(&self) -> String {
+        format!(
+            "#[substorage(v0)]\n{}: {}::Storage,",
+            self.storage, self.path
+        )
+    }
+}
+
+/// A component bound to a user `#[substorage(v0)]` field, declared by the macro when
+/// `auto_components` is set. Its impl names can't be inferred from the field, so one is only
+/// embedded when mapped to the component path in `auto_components(path = Impl)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SubstorageComponent {
+    pub path: String,
+    pub storage: String,
+    pub event: String,
+    pub embed: Option<String>,
+}
+
+impl SubstorageComponent {
+    /// Component of a substorage field of type `path::Storage`, with an event named after
+    /// the field, such as `OwnableEvent` for `ownable`.
+    pub fn from_field(storage: &str, ty: &str) -> Option<Self> {
+        let path = ty.strip_suffix("::Storage")?;
+        let event = storage
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>();
+
+        Some(Self {
+            path: path.to_string(),
+            storage: storage.to_string(),
+            event: format!("{}Event", event),
+            embed: None,
+        })
+    }
+
+    pub fn declaration(&self, state_type: &str) -> String {
+        let declaration = format!(
+            "component!(path: {}, storage: {}, event: {});",
+            self.path, self.storage, self.event
+        );
+
+        match &self.embed {
+            Some(embed) => format!(
+                "{}
+
+    #[abi(embed_v0)]
+    impl {} = {}::{}<{}>;",
+                declaration, embed, self.path, embed, state_type
+            ),
+            None => declaration,
+        }
+    }
+
+    pub fn event_variant(&self) -> String {
+        format!("{}: {}::Event,", self.event, self.path)
+    }
+}
+
+/// Components injected in the contract, in declaration order.
+pub(crate) fn enabled(args: &ContractArgs) -> Vec<&'static Component> {
+    COMPONENTS.iter().filter(|c| c.is_enabled(args)).collect()
+}
+
+/// Components disabled by the contract arguments.
+pub(crate) fn disabled(args: &ContractArgs) -> Vec<&'static Component> {
+    COMPONENTS.iter().filter(|c| !c.is_enabled(args)).collect()
+}
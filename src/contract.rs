@@ -0,0 +1,4058 @@
+use std::collections::HashSet;
+
+use cairo_lang_macro::{Diagnostic, ProcMacroResult, TokenStream};
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::attribute::structured::AttributeStructurize;
+use cairo_lang_syntax::node::ast::{self, MaybeModuleBody, ModuleItem};
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::helpers::{PathSegmentEx, QueryAttrs};
+use cairo_lang_syntax::node::kind::SyntaxKind;
+use cairo_lang_syntax::node::{Terminal, TypedSyntaxNode};
+
+use crate::args::{ContractArgs, InitCheck};
+use crate::component::{self, Component, SubstorageComponent};
+use crate::utils::{
+    braces_balanced, doc_comments, dojo_tag, error_result, is_name_valid, merge_attributes,
+    reindent, to_snake_case, with_separators, SHORT_STRING_MAX_LEN,
+};
+
+const CONTRACT_PATCH: &str = include_str!("patches/contract.patch.cairo");
+const DEFAULT_INIT_PATCH: &str = include_str!("patches/default_init.patch.cairo");
+/// Environment variables pointing to alternate patch files, read at expansion time.
+const CONTRACT_PATCH_ENV: &str = "DOJO_CONTRACT_PATCH";
+const DEFAULT_INIT_PATCH_ENV: &str = "DOJO_DEFAULT_INIT_PATCH";
+const CONSTRUCTOR_FN: &str = "constructor";
+const DOJO_INIT_FN: &str = "dojo_init";
+const DOJO_INIT_IMPL: &str = "IDojoInitImpl";
+const DOJO_INIT_TRAIT: &str = "IDojoInit";
+/// Unchecked init run by the constructor under `init_from_constructor`.
+const DOJO_INIT_INTERNAL_FN: &str = "dojo_init_unchecked";
+const DOJO_INIT_INTERNAL_IMPL: &str = "DojoInitInternalImpl";
+const DOJO_INIT_INTERNAL_TRAIT: &str = "DojoInitInternalTrait";
+const WORLD_HELPER_FN: &str = "world";
+const EXTERNAL_ATTR: &str = "external";
+const STORE_DERIVE: &str = "starknet::Store";
+const INJECTED_WORLD: &str = "let world = self.world_provider.world_dispatcher();";
+const WORLD_DISPATCHER: &str = "self.world_provider.world_dispatcher()";
+const TEST_WORLD_IMPL: &str = "TestWorldImpl";
+const TEST_WORLD_TRAIT: &str = "TestWorldTrait";
+const EMIT_HELPERS_IMPL: &str = "EmitHelpersImpl";
+const EMIT_HELPERS_TRAIT: &str = "EmitHelpersTrait";
+const GENERATED_START_MARKER: &str = "// --- dojo generated ---";
+const GENERATED_END_MARKER: &str = "// --- end ---";
+
+/// Suffixes of names imported for their methods or impls only, such as `StoragePointerReadAccess`
+/// or `IWorldDispatcherTrait`, which are never referenced by name.
+const IMPLICIT_IMPORT_SUFFIXES: &[&str] = &["Trait", "Access", "Entry", "Ext", "Impl"];
+
+/// Types which can't be serialized, and so can't be passed to the `dojo_init` entrypoint,
+/// along with the contract state.
+const NON_SERIALIZABLE_TYPES: &[&str] = &["Felt252Dict", "SquashedFelt252Dict", "Felt252DictEntry"];
+
+/// Statements marking a function as not implemented yet, without whitespace.
+const TODO_PATTERNS: &[&str] = &[
+    "panic!(\"unimplemented\")",
+    "panic!(\"TODO\")",
+    "panic_with_felt252('unimplemented')",
+    "panic_with_felt252('TODO')",
+];
+
+/// Starknet attributes the contract module must not carry, as the patch applies its own.
+const STARKNET_MODULE_ATTRS: &[&str] = &["starknet::contract", "starknet::interface"];
+
+/// Free function names a contract is not allowed to define.
+const RESERVED_FN_NAMES: &[&str] = &["main"];
+
+/// Summary of what the `contract` macro found and generated for a module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "emit-metadata", derive(serde::Serialize))]
+pub struct ContractExpansion {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub has_init: bool,
+    pub has_constructor: bool,
+    pub events: Vec<String>,
+    pub storage_fields: Vec<String>,
+    /// Parameters of `dojo_init` after the contract state, in order, as deployers serialize
+    /// them in the init calldata with `Serde`.
+    pub init_params: Vec<InitParam>,
+    /// Statements of the generated constructor, the injected initializer call included.
+    constructor_statements: Vec<String>,
+}
+
+/// A parameter of `dojo_init`, with its type as written in the signature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "emit-metadata", derive(serde::Serialize))]
+pub struct InitParam {
+    pub name: String,
+    #[cfg_attr(feature = "emit-metadata", serde(rename = "type"))]
+    pub ty: String,
+}
+
+impl ContractExpansion {
+    /// Statements of the generated constructor, in order, for plugins willing to check what
+    /// the contract does at deployment.
+    pub fn constructor_statements(&self) -> &[String] {
+        &self.constructor_statements
+    }
+}
+
+/// Expands a `#[contract]` module given as Cairo source.
+pub fn expand_contract(input: &str) -> ProcMacroResult {
+    expand(&ContractArgs::default(), input)
+}
+
+/// Expands a `#[contract(...)]` module given as Cairo source, with the raw attribute arguments.
+pub fn expand_contract_with_args(attr: &str, input: &str) -> ProcMacroResult {
+    match ContractArgs::parse(attr) {
+        Ok(args) => expand(&args, input),
+        Err(diagnostics) => error_result(input, diagnostics),
+    }
+}
+
+/// Expands a `#[contract]` module given as Cairo source, with arguments parsed with
+/// [`ContractArgs::parse`] or built programmatically.
+pub fn expand_contract_with(args: &ContractArgs, input: &str) -> ProcMacroResult {
+    expand(args, input)
+}
+
+/// Checks a `#[contract(...)]` module, returning the diagnostics of its expansion only.
+///
+/// The checks of the expansion are run without generating any code, so this is cheaper than
+/// an expansion for lint pipelines.
+pub fn validate_contract(attr: &str, input: &str) -> Vec<Diagnostic> {
+    let args = match ContractArgs::parse(attr) {
+        Ok(args) => args,
+        Err(diagnostics) => return diagnostics,
+    };
+
+    let db = SimpleParserDatabase::default();
+    match check(&db, &args, strip_stray_semicolon(input)) {
+        Ok(contract) => contract.warnings,
+        Err(diagnostic) => vec![diagnostic],
+    }
+}
+
+/// A stray `;` after the module body is ignored, so it does not leak into the output.
+fn strip_stray_semicolon(input: &str) -> &str {
+    input
+        .trim_end()
+        .strip_suffix(';')
+        .map(str::trim_end)
+        .filter(|body| body.ends_with('}'))
+        .unwrap_or(input)
+}
+
+/// A contract module which passed the checks, with what the checks found out about it.
+struct CheckedContract {
+    module: ast::ItemModule,
+    name: String,
+    body: ast::ModuleBody,
+    warnings: Vec<Diagnostic>,
+    /// Parameters forwarded by the constructor to `dojo_init`.
+    init_params: Vec<(String, String)>,
+    auto_components: Vec<SubstorageComponent>,
+    /// Local types used by the storage members.
+    storage_types: Vec<String>,
+    /// Variants of the user `Event` enum, the injected ones aside, if there is one.
+    event_variants: Option<Vec<ast::Variant>>,
+    has_storage: bool,
+    /// User storage field holding a world dispatcher, if any.
+    world_field: Option<String>,
+}
+
+/// Runs every check of the contract macro, without generating any code.
+fn check(
+    db: &SimpleParserDatabase,
+    args: &ContractArgs,
+    source: &str,
+) -> Result<CheckedContract, Diagnostic> {
+    let (root, _diagnostics) = db.parse_virtual_with_diagnostics(source);
+
+    let items = ast::SyntaxFile::from_syntax_node(db, root)
+        .items(db)
+        .elements(db);
+
+    let module = items.iter().find_map(|item| match item {
+        ModuleItem::Module(module) => Some(module.clone()),
+        _ => None,
+    });
+
+    // Diagnostics carry no span, so the mis-targeted item is named in the message instead.
+    let Some(module) = module else {
+        let message = if source.trim().is_empty() {
+            "Contract macro expects a module, but was applied to an empty item".to_string()
+        } else {
+            match items.iter().find_map(|item| item_kind(db, item)) {
+                Some((kind, name)) => format!(
+                    "Contract macro can only be applied to modules, but was applied to the {} '{}'",
+                    kind, name
+                ),
+                None => "Contract macro can only be applied to modules".to_string(),
+            }
+        };
+        return Err(Diagnostic::error(message));
+    };
+
+    let name = module.name(db).text(db).to_string();
+
+    // Check module name validity
+    if !is_name_valid(&name) {
+        return Err(Diagnostic::error(format!(
+            "The contract name '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+            name
+        )));
+    }
+
+    // Expanded contracts carry the injected items, which would be injected again.
+    if has_generated_marker(db, &module)
+        || source.contains(&format!("impl {}__ContractImpl ", name))
+    {
+        return Err(Diagnostic::error(format!(
+            "The contract module '{}' is already expanded by the contract macro, which must only be applied once.",
+            name
+        )));
+    }
+
+    // The contract patch already applies the Starknet attribute, which can't be applied twice.
+    if let Some(attr) = STARKNET_MODULE_ATTRS
+        .iter()
+        .find(|attr| module.has_attr(db, attr))
+    {
+        return Err(Diagnostic::error(format!(
+            "The contract module '{}' cannot be annotated with '#[{}]', as the contract macro already applies '#[starknet::contract]'.",
+            name, attr
+        )));
+    }
+
+    let mut warnings = vec![];
+
+    if !args.allow_non_snake_case && name.chars().any(|c| c.is_ascii_uppercase()) {
+        warnings.push(Diagnostic::warn(format!(
+            "The contract name '{}' should be in snake case, consider renaming it to '{}'.",
+            name,
+            to_snake_case(&name)
+        )));
+    }
+
+    if name.len() > SHORT_STRING_MAX_LEN {
+        let message = format!(
+            "The contract name '{}' is longer than {} characters, and doesn't fit in a short string.",
+            name, SHORT_STRING_MAX_LEN
+        );
+        if args.strict_name_length {
+            return Err(Diagnostic::error(message));
+        }
+        warnings.push(Diagnostic::warn(message));
+    }
+
+    let MaybeModuleBody::Some(body) = module.body(db) else {
+        return Err(Diagnostic::error(format!(
+            "The contract module '{}' must have a body.",
+            name
+        )));
+    };
+
+    let components = component::enabled(args);
+    let generated_names = generated_item_names(args, &name, &components);
+
+    // Parameters forwarded by the constructor to the init, which may be defined after it.
+    let init_params = if args.init_from_constructor {
+        body.items(db)
+            .elements(db)
+            .iter()
+            .find_map(|item| match item {
+                ModuleItem::FreeFunction(fn_ast)
+                    if fn_ast.declaration(db).name(db).text(db) == DOJO_INIT_FN =>
+                {
+                    Some(fn_params(db, fn_ast))
+                }
+                _ => None,
+            })
+            .unwrap_or_default()
+    } else {
+        vec![]
+    };
+    let mut event_variants = None;
+    let mut has_storage = false;
+    let mut has_constructor = false;
+    let mut world_field = None;
+    let mut storage_fields: Vec<String> =
+        components.iter().map(|c| c.storage.to_string()).collect();
+    let mut user_components = vec![];
+    let mut embeds = vec![];
+    // Owners of the external functions, `None` for free functions, one per function.
+    let mut external_owners = vec![];
+    let mut unimplemented_fns = vec![];
+    let storage_types = storage_value_types(db, &body);
+
+    let mut auto_components = if args.auto_components {
+        substorage_components(db, &body)
+    } else {
+        vec![]
+    };
+
+    for (path, embed) in &args.auto_component_impls {
+        let Some(component) = auto_components.iter_mut().find(|c| &c.path == path) else {
+            return Err(Diagnostic::error(format!(
+                "The component '{}' of 'auto_components' has no #[substorage(v0)] field in the Storage of contract '{}'.",
+                path, name
+            )));
+        };
+        component.embed = Some(embed.clone());
+    }
+
+    // Components declare their events, which can't be emitted without an `Event` enum.
+    if args.no_event {
+        let evented = components
+            .iter()
+            .map(|c| c.path.to_string())
+            .chain(auto_components.iter().map(|c| c.path.clone()))
+            .map(|path| format!("'{}'", path))
+            .collect::<Vec<_>>();
+
+        if !evented.is_empty() {
+            return Err(Diagnostic::error(format!(
+                "The contract '{}' sets `no_event`, but the components {} emit events.",
+                name,
+                evented.join(", ")
+            )));
+        }
+    }
+
+    for item_ast in body.items(db).elements(db) {
+        match item_ast {
+            ModuleItem::Module(ref nested) => {
+                return Err(Diagnostic::error(format!(
+                    "The contract module '{}' cannot contain nested modules ('{}').",
+                    name,
+                    nested.name(db).text(db)
+                )));
+            }
+            ModuleItem::Impl(ref impl_ast) => {
+                let impl_name = impl_ast.name(db).text(db).to_string();
+                let trait_name = impl_ast
+                    .trait_path(db)
+                    .as_syntax_node()
+                    .get_text_without_trivia(db);
+
+                if let Some(collision) = [impl_name, trait_name]
+                    .into_iter()
+                    .find(|n| generated_names.contains(n))
+                {
+                    return Err(Diagnostic::error(format!(
+                        "The name '{}' in contract '{}' collides with an item generated by the contract macro, please rename it.",
+                        collision, name
+                    )));
+                }
+
+                let is_embedded = is_embedded(db, impl_ast);
+
+                if let ast::MaybeImplBody::Some(impl_body) = impl_ast.body(db) {
+                    for impl_item in impl_body.items(db).elements(db) {
+                        if let ast::ImplItem::Function(fn_ast) = impl_item {
+                            let is_external = fn_ast.has_attr(db, EXTERNAL_ATTR);
+                            if is_external && !args.gen_interface.is_empty() {
+                                check_params(db, &name, &fn_ast)?;
+                            }
+                            if is_external {
+                                external_owners.push(Some(impl_ast.name(db).text(db)));
+                            }
+                            if (is_embedded || is_external) && is_unimplemented(db, &fn_ast) {
+                                unimplemented_fns
+                                    .push(fn_ast.declaration(db).name(db).text(db).to_string());
+                            }
+                        }
+                    }
+                }
+            }
+            ModuleItem::Trait(ref trait_ast) => {
+                let trait_name = trait_ast.name(db).text(db).to_string();
+
+                if generated_names.contains(&trait_name) {
+                    return Err(Diagnostic::error(format!(
+                        "The trait '{}' in contract '{}' collides with the trait generated by the contract macro, please rename it (e.g. '{}Ext').",
+                        trait_name, name, trait_name
+                    )));
+                }
+            }
+            ModuleItem::Enum(ref enum_ast) if enum_ast.name(db).text(db) == "Event" => {
+                if args.no_event {
+                    return Err(Diagnostic::error(format!(
+                        "The contract '{}' sets `no_event`, but defines an 'Event' enum.",
+                        name
+                    )));
+                }
+
+                event_variants = Some(user_event_variants(db, args, &name, enum_ast)?);
+            }
+            ModuleItem::Struct(ref struct_ast) if struct_ast.name(db).text(db) == "Storage" => {
+                has_storage = true;
+                let fields = member_names(db, struct_ast);
+                if let Some(field) = fields
+                    .iter()
+                    .find(|f| components.iter().any(|c| c.storage == f.as_str()))
+                {
+                    return Err(Diagnostic::error(format!(
+                        "The storage field '{}' of contract '{}' collides with the component storage injected by the contract macro, please rename it.",
+                        field, name
+                    )));
+                }
+                world_field = world_dispatcher_field(db, struct_ast);
+                storage_fields.extend(fields);
+            }
+            ModuleItem::FreeFunction(ref fn_ast) => {
+                let fn_name = fn_ast.declaration(db).name(db).text(db);
+                let is_entrypoint = fn_name == CONSTRUCTOR_FN
+                    || fn_name == DOJO_INIT_FN
+                    || fn_ast.has_attr(db, EXTERNAL_ATTR);
+                if is_entrypoint && is_unimplemented(db, fn_ast) {
+                    unimplemented_fns.push(fn_name.to_string());
+                }
+
+                if RESERVED_FN_NAMES.contains(&fn_name.as_str()) {
+                    return Err(Diagnostic::error(format!(
+                        "The contract '{}' cannot define a function named '{}', this name is reserved.",
+                        name, fn_name
+                    )));
+                }
+
+                if (args.world_helper || args.storage_world) && fn_name == WORLD_HELPER_FN {
+                    return Err(Diagnostic::error(format!(
+                        "The contract '{}' already defines a '{}' function, which conflicts with the one generated by `{}`.",
+                        name,
+                        WORLD_HELPER_FN,
+                        if args.world_helper { "world_helper" } else { "storage_world" }
+                    )));
+                }
+
+                // Signatures of entrypoints, and of externals in the generated interface, are
+                // rebuilt from their parameters.
+                if fn_name == CONSTRUCTOR_FN
+                    || fn_name == DOJO_INIT_FN
+                    || (!args.gen_interface.is_empty() && fn_ast.has_attr(db, EXTERNAL_ATTR))
+                {
+                    check_params(db, &name, fn_ast)?;
+                }
+
+                // Entrypoints are called with calldata only, so they can't take generic params.
+                if fn_name == CONSTRUCTOR_FN || fn_name == DOJO_INIT_FN {
+                    if let ast::OptionWrappedGenericParamList::WrappedGenericParamList(generics) =
+                        fn_ast.declaration(db).generic_params(db)
+                    {
+                        return Err(Diagnostic::error(format!(
+                            "The '{}' entrypoint of contract '{}' cannot be generic, but declares '{}'.",
+                            fn_name,
+                            name,
+                            generics.as_syntax_node().get_text_without_trivia(db)
+                        )));
+                    }
+
+                    if args.warn_trailing_return && ends_with_bare_return(db, fn_ast) {
+                        warnings.push(Diagnostic::warn(format!(
+                            "The '{}' of contract '{}' ends with a redundant 'return;'.",
+                            fn_name, name
+                        )));
+                    }
+                }
+
+                if fn_name == CONSTRUCTOR_FN {
+                    if let ast::OptionReturnTypeClause::ReturnTypeClause(ret) =
+                        fn_ast.declaration(db).signature(db).ret_ty(db)
+                    {
+                        let ty = ret.ty(db);
+                        let is_unit = matches!(
+                            &ty,
+                            ast::Expr::Tuple(t) if t.expressions(db).elements(db).is_empty()
+                        );
+                        if !is_unit {
+                            return Err(Diagnostic::error(format!(
+                                "The constructor of contract '{}' cannot return a value, but returns '{}'.",
+                                name,
+                                ty.as_syntax_node().get_text_without_trivia(db)
+                            )));
+                        }
+                    }
+
+                    if args.skip_initializer
+                        && args.world_provider
+                        && !calls_initializer(db, args, fn_ast)
+                    {
+                        warnings.push(missing_initializer_warning(args, &name));
+                    }
+
+                    let ctor_params = fn_params(db, fn_ast);
+                    if let Some((param, ty)) = init_params.iter().find(|p| !ctor_params.contains(p))
+                    {
+                        return Err(Diagnostic::error(format!(
+                            "The constructor of contract '{}' must take the parameter '{}: {}' of '{}', as it is forwarded by `init_from_constructor`.",
+                            name, param, ty, DOJO_INIT_FN
+                        )));
+                    }
+
+                    has_constructor = true;
+                } else if fn_name == DOJO_INIT_FN {
+                    check_init_params(db, args, &name, fn_ast)?;
+                } else if fn_ast.has_attr(db, EXTERNAL_ATTR) {
+                    external_owners.push(None);
+                }
+            }
+            ModuleItem::InlineMacro(ref macro_ast) => {
+                user_components.extend(component_declaration(db, macro_ast));
+            }
+            ModuleItem::ImplAlias(ref alias_ast) => {
+                embeds.push((
+                    alias_ast.name(db).text(db).to_string(),
+                    alias_ast
+                        .impl_path(db)
+                        .as_syntax_node()
+                        .get_text_without_trivia(db),
+                ));
+            }
+            ModuleItem::Struct(ref struct_ast)
+                if storage_types.contains(&struct_ast.name(db).text(db).to_string()) =>
+            {
+                warnings.extend(missing_store_warning(
+                    db,
+                    args,
+                    &struct_ast.attributes(db),
+                    &item_ast,
+                    &name,
+                ));
+            }
+            ModuleItem::Enum(ref enum_ast)
+                if storage_types.contains(&enum_ast.name(db).text(db).to_string()) =>
+            {
+                warnings.extend(missing_store_warning(
+                    db,
+                    args,
+                    &enum_ast.attributes(db),
+                    &item_ast,
+                    &name,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if args.report_todos && !unimplemented_fns.is_empty() {
+        warnings.push(Diagnostic::warn(format!(
+            "The contract '{}' has unimplemented entrypoints: {}.",
+            name,
+            unimplemented_fns
+                .iter()
+                .map(|f| format!("'{}'", f))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+
+    if args.warn_unused_imports {
+        let unused = unused_imports(db, &body.items(db).elements(db));
+        if !unused.is_empty() {
+            warnings.push(Diagnostic::warn(format!(
+                "The contract '{}' has unused imports: {}.",
+                name,
+                unused
+                    .iter()
+                    .map(|i| format!("'{}'", i))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+    }
+
+    // Each component needs its own substorage field.
+    let declared_components = components
+        .iter()
+        .map(|c| (c.path.to_string(), c.storage.to_string()))
+        .chain(user_components.iter().cloned())
+        .chain(
+            auto_components
+                .iter()
+                .map(|c| (c.path.clone(), c.storage.clone())),
+        )
+        .collect::<Vec<_>>();
+
+    for (i, (path, storage)) in declared_components.iter().enumerate() {
+        if let Some((other, _)) = declared_components[..i]
+            .iter()
+            .find(|(p, s)| s == storage && p != path)
+        {
+            return Err(Diagnostic::error(format!(
+                "The components '{}' and '{}' of contract '{}' use the same substorage field '{}', please rename one of them.",
+                other, path, name, storage
+            )));
+        }
+    }
+
+    // Embedded component impls only compile if the component substorage is in `Storage`.
+    let components_storage = component::COMPONENTS
+        .iter()
+        .map(|c| (c.path.to_string(), c.storage.to_string()))
+        .chain(user_components)
+        .chain(
+            auto_components
+                .iter()
+                .map(|c| (c.path.clone(), c.storage.clone())),
+        )
+        .collect::<Vec<_>>();
+
+    for (impl_name, impl_path) in &embeds {
+        let path = impl_path.split("::").next().unwrap_or_default();
+        if let Some((path, storage)) = components_storage.iter().find(|(p, _)| p == path) {
+            if !storage_fields.contains(storage) {
+                return Err(Diagnostic::error(format!(
+                    "The impl '{}' of contract '{}' embeds the component '{}', but its substorage '{}' is missing from Storage.",
+                    impl_name, name, path, storage
+                )));
+            }
+        }
+    }
+
+    if has_constructor && args.default_ctor_body.is_some() {
+        return Err(Diagnostic::error(format!(
+            "The contract '{}' defines a constructor, so `default_ctor_body` is unused, please move its statements into the constructor.",
+            name
+        )));
+    }
+
+    if !has_constructor && args.skip_initializer && args.world_provider {
+        warnings.push(missing_initializer_warning(args, &name));
+    }
+
+    if args.gen_interface.len() > 1 {
+        let mut owners = vec![];
+        for owner in external_owners {
+            if !owners.contains(&owner) {
+                owners.push(owner);
+            }
+        }
+        let groups = owners.len();
+
+        if groups != args.gen_interface.len() {
+            return Err(Diagnostic::error(format!(
+                "The contract '{}' lists {} interfaces in 'gen_interface', but has {} groups of external functions: one per impl, and one for the free functions.",
+                name,
+                args.gen_interface.len(),
+                groups
+            )));
+        }
+    }
+
+    if args.storage_world && world_field.is_none() {
+        warnings.push(Diagnostic::warn(format!(
+            "The contract '{}' sets `storage_world`, but has no storage field of type 'IWorldDispatcher'.",
+            name
+        )));
+    }
+
+    Ok(CheckedContract {
+        module,
+        name,
+        body,
+        warnings,
+        init_params,
+        auto_components,
+        storage_types,
+        event_variants,
+        has_storage,
+        world_field,
+    })
+}
+
+pub(crate) fn expand(args: &ContractArgs, input: &str) -> ProcMacroResult {
+    // Error results still carry the item as given.
+    let source = strip_stray_semicolon(input);
+
+    let patches = load_patch(CONTRACT_PATCH_ENV, CONTRACT_PATCH, &["$name$", "$body$"]).and_then(
+        |contract| {
+            load_patch(DEFAULT_INIT_PATCH_ENV, DEFAULT_INIT_PATCH, &["$init_name$"])
+                .map(|init| (contract, init))
+        },
+    );
+    let (contract_patch, default_init_patch) = match patches {
+        Ok(patches) => patches,
+        Err(diagnostic) => return error_result(input, diagnostic),
+    };
+
+    let db = SimpleParserDatabase::default();
+    let CheckedContract {
+        module,
+        name,
+        body,
+        warnings,
+        init_params,
+        auto_components,
+        storage_types,
+        event_variants,
+        has_storage,
+        world_field,
+    } = match check(&db, args, source) {
+        Ok(contract) => contract,
+        Err(diagnostic) => return error_result(input, diagnostic),
+    };
+
+    let components = component::enabled(args);
+
+    let mut expansion = ContractExpansion {
+        name: name.clone(),
+        namespace: args.namespace.clone(),
+        events: components.iter().map(|c| c.event.to_string()).collect(),
+        storage_fields: components.iter().map(|c| c.storage.to_string()).collect(),
+        ..Default::default()
+    };
+
+    // Process module body
+    let mut body_nodes = Vec::new();
+    let mut external_fns = vec![];
+
+    for item_ast in body.items(&db).elements(&db) {
+        match item_ast {
+            ModuleItem::Impl(ref impl_ast) => {
+                let is_embedded = is_embedded(&db, impl_ast);
+                let mut impl_text = item_ast.as_syntax_node().get_text(&db);
+
+                if let ast::MaybeImplBody::Some(impl_body) = impl_ast.body(&db) {
+                    for impl_item in impl_body.items(&db).elements(&db) {
+                        if let ast::ImplItem::Function(fn_ast) = impl_item {
+                            let is_external = fn_ast.has_attr(&db, EXTERNAL_ATTR);
+                            if is_external {
+                                external_fns.push((
+                                    Some(impl_ast.name(&db).text(&db)),
+                                    interface_signature(&db, args, &fn_ast),
+                                ));
+                            }
+                            if args.inject_world && (is_embedded || is_external) {
+                                if let Some(injected) = inject_world(&db, &fn_ast) {
+                                    impl_text = impl_text.replacen(
+                                        &fn_ast.as_syntax_node().get_text(&db),
+                                        &injected,
+                                        1,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+
+                body_nodes.push(impl_text);
+            }
+            ModuleItem::Enum(ref enum_ast) if enum_ast.name(&db).text(&db) == "Event" => {
+                let variants = event_variants.as_deref().unwrap_or_default();
+                expansion
+                    .events
+                    .extend(variants.iter().map(|v| v.name(&db).text(&db).to_string()));
+                // Add processed event node
+                body_nodes.push(process_event(
+                    &db,
+                    &components,
+                    &auto_components,
+                    enum_ast,
+                    variants,
+                ));
+            }
+            ModuleItem::Struct(ref struct_ast) if struct_ast.name(&db).text(&db) == "Storage" => {
+                expansion
+                    .storage_fields
+                    .extend(member_names(&db, struct_ast));
+                // Add processed storage node
+                body_nodes.push(process_storage(&db, &components, struct_ast));
+            }
+            ModuleItem::FreeFunction(ref fn_ast) => {
+                let fn_name = fn_ast.declaration(&db).name(&db).text(&db);
+
+                if fn_name == CONSTRUCTOR_FN {
+                    expansion.has_constructor = true;
+                    expansion.constructor_statements = initializer_call(args)
+                        .into_iter()
+                        .chain(init_call(args, &init_params))
+                        .chain(
+                            fn_ast
+                                .body(&db)
+                                .statements(&db)
+                                .elements(&db)
+                                .iter()
+                                .map(|stmt| stmt.as_syntax_node().get_text_without_trivia(&db)),
+                        )
+                        .collect();
+                    // Add processed constructor
+                    body_nodes.extend(process_constructor(&db, args, &init_params, fn_ast));
+                } else if fn_name == DOJO_INIT_FN {
+                    expansion.has_init = true;
+                    expansion.init_params = fn_params(&db, fn_ast)
+                        .into_iter()
+                        .map(|(name, ty)| InitParam { name, ty })
+                        .collect();
+                    // Add processed init function
+                    body_nodes.extend(process_init(&db, args, fn_ast));
+                } else {
+                    let is_external = fn_ast.has_attr(&db, EXTERNAL_ATTR);
+                    if is_external {
+                        external_fns.push((None, interface_signature(&db, args, fn_ast)));
+                    }
+                    body_nodes.push(
+                        (args.inject_world && is_external)
+                            .then(|| inject_world(&db, fn_ast))
+                            .flatten()
+                            .unwrap_or_else(|| item_ast.as_syntax_node().get_text(&db)),
+                    );
+                }
+            }
+            ModuleItem::Struct(ref struct_ast)
+                if storage_types.contains(&struct_ast.name(&db).text(&db).to_string()) =>
+            {
+                body_nodes.push(storage_value_type(
+                    &db,
+                    args,
+                    &struct_ast.attributes(&db),
+                    &item_ast,
+                ));
+            }
+            ModuleItem::Enum(ref enum_ast)
+                if storage_types.contains(&enum_ast.name(&db).text(&db).to_string()) =>
+            {
+                body_nodes.push(storage_value_type(
+                    &db,
+                    args,
+                    &enum_ast.attributes(&db),
+                    &item_ast,
+                ));
+            }
+            _ => body_nodes.push(item_ast.as_syntax_node().get_text(&db)),
+        }
+    }
+
+    // Add default implementations if missing
+    let defaults_start = body_nodes.len();
+
+    body_nodes.extend(
+        auto_components
+            .iter()
+            .map(|c| c.declaration(args.state_type())),
+    );
+
+    if !expansion.has_constructor {
+        expansion.constructor_statements = initializer_call(args)
+            .into_iter()
+            .chain(init_call(args, &init_params))
+            .chain(
+                args.default_ctor_body
+                    .iter()
+                    .map(|body| body.trim().to_string()),
+            )
+            .collect();
+        // The extra statements end with a newline, so a trailing comment can't swallow the brace.
+        body_nodes.push(format!(
+            "
+            #[constructor]
+            fn constructor(ref self: {}{}) {{
+                {}
+                {}
+                {}
+            }}
+            ",
+            args.state_type(),
+            init_params
+                .iter()
+                .map(|(param, ty)| format!(", {}: {}", param, ty))
+                .collect::<String>(),
+            initializer_call(args).unwrap_or_default(),
+            init_call(args, &init_params).unwrap_or_default(),
+            args.default_ctor_body.as_deref().unwrap_or_default()
+        ));
+    }
+
+    if !expansion.has_init {
+        let (init_header, init_fn_attrs) = init_abi(
+            args,
+            &format!("fn {}(self: @TContractState);", DOJO_INIT_FN),
+        );
+        body_nodes.push(
+            default_init_patch
+                .replace("$init_header$", &init_header)
+                .replace("$init_fn_attrs$", init_fn_attrs)
+                .replace("$init_name$", DOJO_INIT_FN)
+                .replace("$init_caller_check$", &init_caller_check(args))
+                .replace("$state_type$", args.state_type())
+                .replace(
+                    "$init_assert$",
+                    &init_call(args, &[]).unwrap_or_else(|| init_assert(args)),
+                ),
+        );
+        if args.init_from_constructor {
+            body_nodes.push(init_internal(
+                args,
+                &format!("self: @{}", args.state_type()),
+                &[],
+            ));
+        }
+    }
+
+    if event_variants.is_none() && !args.no_event {
+        body_nodes.push(format!(
+            "
+            #[event]
+            #[derive(Drop, starknet::Event)]
+            enum Event {{
+                {}
+                {}
+            }}
+            ",
+            component_events(&components),
+            auto_components
+                .iter()
+                .map(|c| c.event_variant())
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+
+    if !has_storage {
+        body_nodes.push(format!(
+            "
+            #[storage]
+            struct Storage {{
+                {}
+            }}
+            ",
+            component_storage(&components)
+        ));
+    }
+
+    // Interfaces are declared in the contract module, along with their dispatchers.
+    let mut groups: Vec<(_, Vec<String>)> = vec![];
+    for (owner, signature) in external_fns {
+        let owner = if args.gen_interface.len() > 1 {
+            owner
+        } else {
+            None
+        };
+        match groups.iter_mut().find(|(o, _)| *o == owner) {
+            Some((_, signatures)) => signatures.push(signature),
+            None => groups.push((owner, vec![signature])),
+        }
+    }
+
+    for (i, interface) in args.gen_interface.iter().enumerate() {
+        body_nodes.push(format!(
+            "
+            #[starknet::interface]
+            pub trait {}<TContractState> {{
+                {}
+            }}
+            ",
+            interface,
+            groups
+                .get(i)
+                .map(|(_, signatures)| signatures.join("\n"))
+                .unwrap_or_default()
+        ));
+    }
+
+    if args.world_helper {
+        body_nodes.push(format!(
+            "
+            fn {}(self: @{}) -> dojo::world::IWorldDispatcher {{
+                {}
+            }}
+            ",
+            WORLD_HELPER_FN,
+            args.state_type(),
+            WORLD_DISPATCHER
+        ));
+    }
+
+    if let (true, Some(field)) = (args.storage_world, &world_field) {
+        body_nodes.push(format!(
+            "
+            use starknet::storage::StoragePointerReadAccess as StorageWorldRead;
+
+            fn {}(self: @{}) -> dojo::world::IWorldDispatcher {{
+                self.{}.read()
+            }}
+            ",
+            WORLD_HELPER_FN,
+            args.state_type(),
+            field
+        ));
+    }
+
+    // Component events, as (variant, type), each emitted by its own helper.
+    let component_events = components
+        .iter()
+        .map(|c| (c.event.to_string(), c.event_type()))
+        .chain(
+            auto_components
+                .iter()
+                .map(|c| (c.event.clone(), format!("{}::Event", c.path))),
+        )
+        .collect::<Vec<_>>();
+
+    if args.emit_helpers && !component_events.is_empty() {
+        body_nodes.push(format!(
+            "
+            #[generate_trait]
+            impl {} of {} {{
+                {}
+            }}
+            ",
+            EMIT_HELPERS_IMPL,
+            EMIT_HELPERS_TRAIT,
+            component_events
+                .iter()
+                .map(|(variant, ty)| format!(
+                    "fn emit_{}(ref self: {}, event: {}) {{
+                    self.emit(Event::{}(event));
+                }}",
+                    to_snake_case(variant),
+                    args.state_type(),
+                    ty,
+                    variant
+                ))
+                .collect::<Vec<_>>()
+                .join("\n\n                ")
+        ));
+    }
+
+    // Only compiled in tests, so unit tests can point the contract to a world without deploying it.
+    if args.test_world {
+        body_nodes.push(format!(
+            "
+            #[cfg(test)]
+            use starknet::storage::StoragePointerWriteAccess as TestWorldStorageWrite;
+
+            #[cfg(test)]
+            #[generate_trait]
+            pub impl {} of {} {{
+                fn set_test_world(ref self: {}, world: dojo::world::IWorldDispatcher) {{
+                    self.world_provider.world_dispatcher.write(world);
+                }}
+            }}
+            ",
+            TEST_WORLD_IMPL,
+            TEST_WORLD_TRAIT,
+            args.state_type()
+        ));
+    }
+
+    // Injected items are bracketed by comment markers, so they can be located in the output.
+    if body_nodes.len() > defaults_start {
+        let defaults = body_nodes.split_off(defaults_start).join("\n");
+        body_nodes.push(generated(defaults));
+    }
+
+    // Combine body nodes
+    let merged_body = body_nodes.join("\n");
+
+    // Raw expansions stop at the merged body, defaults included, for composition elsewhere.
+    let final_code = if args.raw {
+        merged_body
+    } else {
+        apply_contract_patch(
+            &db,
+            args,
+            &contract_patch,
+            &components,
+            &module,
+            &name,
+            &merged_body,
+        )
+    };
+
+    let final_code = reindent(&final_code, args.indent);
+
+    // Safety net for the string based generation: unbalanced braces would only fail far
+    // downstream, so the first user item with unbalanced braces is reported instead.
+    if !braces_balanced(&final_code) {
+        let culprit = body
+            .items(&db)
+            .elements(&db)
+            .iter()
+            .map(|item| item.as_syntax_node().get_text_without_trivia(&db))
+            .find(|text| !braces_balanced(text));
+
+        return error_result(
+            input,
+            Diagnostic::error(match culprit {
+                Some(item) => format!(
+                    "Internal error: the expansion of contract '{}' has unbalanced braces, likely caused by the item '{}'.",
+                    name,
+                    item.lines().next().unwrap_or_default()
+                ),
+                None => format!(
+                    "Internal error: the expansion of contract '{}' has unbalanced braces.",
+                    name
+                ),
+            }),
+        );
+    }
+
+    let result =
+        ProcMacroResult::new(TokenStream::new(final_code)).with_diagnostics(warnings.into());
+
+    #[cfg(feature = "emit-metadata")]
+    let result = result.with_aux_data(crate::manifest::contract_aux_data(&expansion));
+
+    result
+}
+
+/// Wraps the processed body into the contract patch.
+fn apply_contract_patch(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    contract_patch: &str,
+    components: &[&Component],
+    module: &ast::ItemModule,
+    name: &str,
+    body: &str,
+) -> String {
+    // The regenerated module keeps the visibility it was declared with.
+    let visibility = match module
+        .visibility(db)
+        .as_syntax_node()
+        .get_text_without_trivia(db)
+    {
+        v if v.is_empty() => v,
+        v => format!("{} ", v),
+    };
+
+    let module_attrs = args
+        .module_attrs
+        .iter()
+        .map(|attr| format!("#[{}]", attr))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // The world storage is built from the world provider, when it is enabled. With a default
+    // namespace, it can be built without passing the namespace around.
+    let world_storage = if args.world_provider {
+        let world_default = match &args.namespace {
+            Some(namespace) => format!(
+                "
+        fn world_default(self: @{}) -> dojo::world::storage::WorldStorage {{
+            self.world(@\"{}\")
+        }}",
+                args.state_type(),
+                namespace
+            ),
+            None => String::new(),
+        };
+
+        format!(
+            "fn world(self: @{}, namespace: @ByteArray) -> dojo::world::storage::WorldStorage {{
+            dojo::world::WorldStorageTrait::new({}, namespace)
+        }}{}",
+            args.state_type(),
+            WORLD_DISPATCHER,
+            world_default
+        )
+    } else {
+        String::new()
+    };
+
+    let cfg = match &args.cfg {
+        Some(predicate) => format!("#[cfg({})]\n", predicate),
+        None => String::new(),
+    };
+
+    // Generate final code using the contract patch. User provided text is substituted last,
+    // around the body, so literals such as `"$name$"` are kept as is.
+    let patch = contract_patch
+        .replace(
+            "$contract_flavor$",
+            if args.account { "(account)" } else { "" },
+        )
+        .replace("$world_storage$", &world_storage)
+        .replace("$visibility$", &visibility)
+        .replace(
+            "$components$",
+            &generated(
+                components
+                    .iter()
+                    .map(|c| c.declaration(args.state_type(), args.auto_import))
+                    .collect::<Vec<_>>()
+                    .join("\n    "),
+            ),
+        )
+        .replace("$state_type$", args.state_type())
+        .replace("$dojo_name$", &dojo_tag(args.namespace.as_deref(), name))
+        .replace("$name$", name);
+    let (head, tail) = patch.split_once("$body$").unwrap_or((&patch, ""));
+
+    // The body may end with a line comment, which must not swallow the rest of the patch.
+    format!(
+        "{}{}\n{}",
+        head.replace("$cfg$", &cfg)
+            .replace("$module_attrs$", &module_attrs),
+        body,
+        tail
+    )
+}
+
+/// Whether the generated start marker is a comment line of the module body itself, rather
+/// than text found in one of its items, such as a string literal or a nested comment.
+fn has_generated_marker(db: &dyn SyntaxGroup, module: &ast::ItemModule) -> bool {
+    let MaybeModuleBody::Some(body) = module.body(db) else {
+        return false;
+    };
+
+    // Comments of the body are in the leading trivia of the item following them.
+    body.items(db).elements(db).iter().any(|item| {
+        item.as_syntax_node()
+            .get_text(db)
+            .lines()
+            .map(str::trim)
+            .take_while(|line| line.is_empty() || line.starts_with("//"))
+            .any(|line| line == GENERATED_START_MARKER)
+    })
+}
+
+/// Whether the impl is embedded in the contract ABI.
+fn is_embedded(db: &dyn SyntaxGroup, impl_ast: &ast::ItemImpl) -> bool {
+    impl_ast
+        .attributes(db)
+        .as_syntax_node()
+        .get_text(db)
+        .contains("embed_v0")
+}
+
+/// Kind and name of a module item, for items which are named.
+fn item_kind(db: &dyn SyntaxGroup, item: &ModuleItem) -> Option<(&'static str, String)> {
+    let (kind, name) = match item {
+        ModuleItem::Constant(item) => ("constant", item.name(db)),
+        ModuleItem::FreeFunction(item) => ("function", item.declaration(db).name(db)),
+        ModuleItem::ExternFunction(item) => ("extern function", item.declaration(db).name(db)),
+        ModuleItem::ExternType(item) => ("extern type", item.name(db)),
+        ModuleItem::Trait(item) => ("trait", item.name(db)),
+        ModuleItem::Impl(item) => ("impl", item.name(db)),
+        ModuleItem::ImplAlias(item) => ("impl alias", item.name(db)),
+        ModuleItem::Struct(item) => ("struct", item.name(db)),
+        ModuleItem::Enum(item) => ("enum", item.name(db)),
+        ModuleItem::TypeAlias(item) => ("type alias", item.name(db)),
+        _ => return None,
+    };
+    Some((kind, name.text(db).to_string()))
+}
+
+/// Contents of the alternate patches set in the environment, if any.
+pub(crate) fn patch_overrides() -> String {
+    [CONTRACT_PATCH_ENV, DEFAULT_INIT_PATCH_ENV]
+        .iter()
+        .filter_map(|env_var| std::env::var(env_var).ok().map(|path| (env_var, path)))
+        .map(|(env_var, path)| {
+            let patch = std::fs::read_to_string(&path).unwrap_or_default();
+            format!("{}={}\n{}", env_var, path, patch)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Loads the patch from the file pointed by `env_var` if set, or returns the built-in one.
+///
+/// Alternate patches must contain the `required` placeholders, which the expansion relies on.
+fn load_patch(env_var: &str, default: &str, required: &[&str]) -> Result<String, Diagnostic> {
+    let Ok(path) = std::env::var(env_var) else {
+        return Ok(default.to_string());
+    };
+
+    let patch = std::fs::read_to_string(&path).map_err(|err| {
+        Diagnostic::error(format!(
+            "Failed to read the patch file '{}' set by `{}`: {}.",
+            path, env_var, err
+        ))
+    })?;
+
+    match required.iter().find(|p| !patch.contains(**p)) {
+        Some(placeholder) => Err(Diagnostic::error(format!(
+            "The patch file '{}' set by `{}` is missing the placeholder '{}'.",
+            path, env_var, placeholder
+        ))),
+        None => Ok(patch),
+    }
+}
+
+/// Names of the impls and traits generated in every contract, which user items must not reuse.
+fn generated_item_names(
+    args: &ContractArgs,
+    contract_name: &str,
+    components: &[&Component],
+) -> Vec<String> {
+    let mut names = vec![
+        DOJO_INIT_IMPL.to_string(),
+        DOJO_INIT_TRAIT.to_string(),
+        format!("{}__ContractImpl", contract_name),
+        format!("{}__DeployedContractImpl", contract_name),
+        format!("{}InternalImpl", contract_name),
+        format!("{}InternalTrait", contract_name),
+    ];
+    names.extend(components.iter().map(|c| c.embed.0.to_string()));
+    if args.test_world {
+        names.extend([TEST_WORLD_IMPL.to_string(), TEST_WORLD_TRAIT.to_string()]);
+    }
+    if args.emit_helpers {
+        names.extend([
+            EMIT_HELPERS_IMPL.to_string(),
+            EMIT_HELPERS_TRAIT.to_string(),
+        ]);
+    }
+    if args.init_from_constructor {
+        names.extend([
+            DOJO_INIT_INTERNAL_IMPL.to_string(),
+            DOJO_INIT_INTERNAL_TRAIT.to_string(),
+        ]);
+    }
+    names
+}
+
+/// Variants of the user `Event` enum to merge with the injected ones.
+///
+/// Variants are kept as written, nested events and `#[flat]` included. A user variant
+/// re-declaring an injected component event is dropped, unless its type doesn't match.
+/// Variants referencing a disabled component are rejected, as they would not compile.
+fn user_event_variants(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    contract_name: &str,
+    enum_ast: &ast::ItemEnum,
+) -> Result<Vec<ast::Variant>, Diagnostic> {
+    let mut variants = vec![];
+
+    for variant in enum_ast.variants(db).elements(db) {
+        let name = variant.name(db).text(db);
+        let ty = match variant.type_clause(db) {
+            ast::OptionTypeClause::TypeClause(clause) => {
+                clause.ty(db).as_syntax_node().get_text_without_trivia(db)
+            }
+            ast::OptionTypeClause::Empty(_) => String::new(),
+        };
+
+        if let Some(disabled) = component::disabled(args)
+            .into_iter()
+            .find(|c| c.is_referenced_by(&ty))
+        {
+            return Err(Diagnostic::error(format!(
+                "The event variant '{}' of contract '{}' references the component '{}', which is disabled by `{} = false`.",
+                name, contract_name, disabled.path, disabled.arg
+            )));
+        }
+
+        match component::enabled(args)
+            .into_iter()
+            .find(|c| c.event == name)
+        {
+            Some(c) if ty == c.event_type() => {}
+            Some(c) => {
+                return Err(Diagnostic::error(format!(
+                    "The event variant '{}' is injected by the contract macro with type '{}', and cannot be redefined.",
+                    name,
+                    c.event_type()
+                )));
+            }
+            None => variants.push(variant),
+        }
+    }
+
+    Ok(variants)
+}
+
+/// Component path and storage field declared by a user `component!(...)` item.
+fn component_declaration(
+    db: &dyn SyntaxGroup,
+    macro_ast: &ast::ItemInlineMacro,
+) -> Option<(String, String)> {
+    if macro_ast.name(db).text(db) != "component" {
+        return None;
+    }
+
+    let ast::WrappedArgList::ParenthesizedArgList(args) = macro_ast.arguments(db) else {
+        return None;
+    };
+
+    let mut path = None;
+    let mut storage = None;
+
+    for arg in args.arguments(db).elements(db) {
+        if let ast::ArgClause::Named(arg) = arg.arg_clause(db) {
+            let value = arg.value(db).as_syntax_node().get_text_without_trivia(db);
+            match arg.name(db).text(db).as_str() {
+                "path" => path = Some(value),
+                "storage" => storage = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    path.zip(storage)
+}
+
+fn member_names(db: &dyn SyntaxGroup, struct_ast: &ast::ItemStruct) -> Vec<String> {
+    struct_ast
+        .members(db)
+        .elements(db)
+        .iter()
+        .map(|m| m.name(db).text(db).to_string())
+        .collect()
+}
+
+/// Brackets injected code with the generated markers, unless there is nothing injected.
+///
+/// Besides the defaults of the module body, the markers bracket the component declarations
+/// and the members injected into the user `Storage` and `Event`. The fixed items of the
+/// contract patch, such as the `IContract` impl, are left out.
+fn generated(code: String) -> String {
+    if code.trim().is_empty() {
+        code
+    } else {
+        format!(
+            "{}\n{}\n{}",
+            GENERATED_START_MARKER, code, GENERATED_END_MARKER
+        )
+    }
+}
+
+fn component_events(components: &[&Component]) -> String {
+    components
+        .iter()
+        .map(|c| c.event_variant())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn component_storage(components: &[&Component]) -> String {
+    components
+        .iter()
+        .map(|c| c.storage_member())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn process_event(
+    db: &dyn SyntaxGroup,
+    components: &[&Component],
+    auto_components: &[SubstorageComponent],
+    enum_ast: &ast::ItemEnum,
+    variants: &[ast::Variant],
+) -> String {
+    // An empty user enum degrades to the component events only.
+    let mut injected = vec![component_events(components)];
+
+    // Events of substorage components are only added if the user didn't declare them.
+    injected.extend(
+        auto_components
+            .iter()
+            .filter(|c| !variants.iter().any(|v| v.name(db).text(db) == c.event))
+            .map(|c| c.event_variant()),
+    );
+
+    let mut all_variants = vec![generated(injected.join("\n"))];
+
+    if !variants.is_empty() {
+        let kept = variants
+            .iter()
+            .map(|v| v.as_syntax_node())
+            .collect::<Vec<_>>();
+        all_variants.push(
+            with_separators(db, &enum_ast.variants(db).as_syntax_node())
+                .into_iter()
+                .filter(|(node, _)| kept.contains(node))
+                .map(|(_, text)| text)
+                .collect(),
+        );
+    }
+
+    let attributes = merge_attributes(
+        db,
+        enum_ast.attributes(db),
+        &["event"],
+        &["Drop", "starknet::Event"],
+    );
+
+    format!(
+        "
+        {}
+        enum Event {{
+            {}
+        }}
+        ",
+        attributes,
+        all_variants.join("\n")
+    )
+}
+
+/// Components of the `#[substorage(v0)]` fields of the user `Storage` struct, which are not
+/// declared with `component!` already.
+fn substorage_components(db: &dyn SyntaxGroup, body: &ast::ModuleBody) -> Vec<SubstorageComponent> {
+    let items = body.items(db).elements(db);
+
+    let declared = items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::InlineMacro(macro_ast) => component_declaration(db, macro_ast),
+            _ => None,
+        })
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+
+    let Some(storage) = items.iter().find_map(|item| match item {
+        ModuleItem::Struct(struct_ast) if struct_ast.name(db).text(db) == "Storage" => {
+            Some(struct_ast)
+        }
+        _ => None,
+    }) else {
+        return vec![];
+    };
+
+    storage
+        .members(db)
+        .elements(db)
+        .iter()
+        .filter(|m| m.has_attr(db, "substorage"))
+        .filter_map(|m| {
+            SubstorageComponent::from_field(
+                &m.name(db).text(db),
+                &m.type_clause(db)
+                    .ty(db)
+                    .as_syntax_node()
+                    .get_text_without_trivia(db),
+            )
+        })
+        .filter(|c| !declared.contains(&c.path))
+        .collect()
+}
+
+/// Name of the first user storage field holding a world dispatcher, if any.
+fn world_dispatcher_field(db: &dyn SyntaxGroup, struct_ast: &ast::ItemStruct) -> Option<String> {
+    struct_ast.members(db).elements(db).iter().find_map(|m| {
+        let ty = m
+            .type_clause(db)
+            .ty(db)
+            .as_syntax_node()
+            .get_text_without_trivia(db);
+        (ty.rsplit("::").next() == Some("IWorldDispatcher"))
+            .then(|| m.name(db).text(db).to_string())
+    })
+}
+
+/// Prepends the component substorage to the user `Storage` struct.
+fn process_storage(
+    db: &dyn SyntaxGroup,
+    components: &[&Component],
+    struct_ast: &ast::ItemStruct,
+) -> String {
+    let members = with_separators(db, &struct_ast.members(db).as_syntax_node())
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect::<String>();
+
+    let attributes = merge_attributes(db, struct_ast.attributes(db), &["storage"], &[]);
+
+    format!(
+        "
+        {}
+        struct Storage {{
+            {}
+            {}
+        }}
+        ",
+        attributes,
+        generated(component_storage(components)),
+        members
+    )
+}
+
+/// Signature of an external function in the generated interface trait, generic over the
+/// contract state.
+fn interface_signature(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    fn_ast: &ast::FunctionWithBody,
+) -> String {
+    let declaration = fn_ast.declaration(db);
+    let signature = declaration.signature(db);
+
+    let params = signature
+        .parameters(db)
+        .elements(db)
+        .iter()
+        .map(|param| {
+            let text = param.as_syntax_node().get_text_without_trivia(db);
+            let ty = match param.type_clause(db) {
+                ast::OptionTypeClause::TypeClause(clause) => {
+                    clause.ty(db).as_syntax_node().get_text_without_trivia(db)
+                }
+                ast::OptionTypeClause::Empty(_) => String::new(),
+            };
+
+            if param.name(db).text(db) == "self" && ty.trim_start_matches('@') == args.state_type()
+            {
+                text.replace(args.state_type(), "TContractState")
+            } else {
+                text
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "fn {}{}({}){};",
+        declaration.name(db).text(db),
+        declaration
+            .generic_params(db)
+            .as_syntax_node()
+            .get_text_without_trivia(db),
+        params,
+        match signature.ret_ty(db) {
+            ast::OptionReturnTypeClause::ReturnTypeClause(ret) => {
+                format!(" {}", ret.as_syntax_node().get_text_without_trivia(db))
+            }
+            ast::OptionReturnTypeClause::Empty(_) => String::new(),
+        }
+    )
+}
+
+/// World provider initialization, first statement of every constructor, unless the world
+/// provider component is disabled or its initialization is left to the user.
+fn initializer_call(args: &ContractArgs) -> Option<String> {
+    (args.world_provider && !args.skip_initializer)
+        .then(|| format!("self.world_provider.{}();", args.initializer()))
+}
+
+/// Rewrites an external function to bind `world` at the top of its body, unless it doesn't
+/// take `self` or already binds `world` itself.
+fn inject_world(db: &dyn SyntaxGroup, fn_ast: &ast::FunctionWithBody) -> Option<String> {
+    let takes_self = fn_ast
+        .declaration(db)
+        .signature(db)
+        .parameters(db)
+        .elements(db)
+        .first()
+        .is_some_and(|p| p.name(db).as_syntax_node().get_text_without_trivia(db) == "self");
+
+    let binds_world = fn_ast
+        .body(db)
+        .statements(db)
+        .elements(db)
+        .iter()
+        .any(|stmt| match stmt {
+            ast::Statement::Let(let_stmt) => {
+                let_stmt
+                    .pattern(db)
+                    .as_syntax_node()
+                    .get_text_without_trivia(db)
+                    == "world"
+            }
+            _ => false,
+        });
+
+    if !takes_self || binds_world {
+        return None;
+    }
+
+    // The body is the last child of the function, so its brace is found from the end.
+    let text = fn_ast.as_syntax_node().get_text(db);
+    let body = fn_ast.body(db);
+    let body_text = body.as_syntax_node().get_text(db);
+    let lbrace = body.lbrace(db).as_syntax_node().get_text(db);
+    let (head, body_text) = text.split_at(text.len() - body_text.len());
+
+    Some(format!(
+        "{}{}{}\n{}",
+        head,
+        lbrace,
+        INJECTED_WORLD,
+        &body_text[lbrace.len()..]
+    ))
+}
+
+/// Names of the local structs and enums used by the storage members, which must implement
+/// `starknet::Store`. Types with a hand-written `Store` impl are left out.
+fn storage_value_types(db: &dyn SyntaxGroup, body: &ast::ModuleBody) -> Vec<String> {
+    let items = body.items(db).elements(db);
+
+    let member_types = items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::Struct(struct_ast) if struct_ast.name(db).text(db) == "Storage" => {
+                Some(struct_ast.members(db).elements(db))
+            }
+            _ => None,
+        })
+        .flatten()
+        .map(|m| {
+            m.type_clause(db)
+                .ty(db)
+                .as_syntax_node()
+                .get_text_without_trivia(db)
+        })
+        .collect::<Vec<_>>();
+
+    let store_impls = items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::Impl(impl_ast) => Some(
+                impl_ast
+                    .trait_path(db)
+                    .as_syntax_node()
+                    .get_text_without_trivia(db),
+            ),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    items
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::Struct(struct_ast) => Some(struct_ast.name(db).text(db).to_string()),
+            ModuleItem::Enum(enum_ast) => Some(enum_ast.name(db).text(db).to_string()),
+            _ => None,
+        })
+        .filter(|ty| ty != "Storage" && ty != "Event")
+        .filter(|ty| {
+            member_types.iter().any(|t| {
+                t.split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .any(|segment| segment == ty)
+            })
+        })
+        .filter(|ty| {
+            !store_impls
+                .iter()
+                .any(|t| t.ends_with(&format!("Store<{}>", ty)))
+        })
+        .collect()
+}
+
+/// Whether the given attributes derive `Store`.
+fn derives_store(db: &dyn SyntaxGroup, attributes: &ast::AttributeList) -> bool {
+    attributes
+        .elements(db)
+        .into_iter()
+        .map(|attr| attr.structurize(db))
+        .filter(|attr| attr.id == "derive")
+        .flat_map(|attr| attr.args.into_iter().map(|arg| arg.text(db)))
+        .any(|derive| derive == "Store" || derive.ends_with("::Store"))
+}
+
+/// Warning on a storage value type which doesn't derive `Store`, unless it's derived by
+/// `derive_store`.
+fn missing_store_warning(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    attributes: &ast::AttributeList,
+    item_ast: &ModuleItem,
+    contract_name: &str,
+) -> Option<Diagnostic> {
+    if derives_store(db, attributes) || args.derive_store {
+        return None;
+    }
+
+    let ty = item_kind(db, item_ast)
+        .map(|(_, ty)| ty)
+        .unwrap_or_default();
+    Some(Diagnostic::warn(format!(
+        "The type '{}' is used in the storage of contract '{}', but doesn't derive '{}': set `derive_store` to derive it.",
+        ty, contract_name, STORE_DERIVE
+    )))
+}
+
+/// Emits a local storage value type, deriving `starknet::Store` on it under `derive_store`.
+fn storage_value_type(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    attributes: &ast::AttributeList,
+    item_ast: &ModuleItem,
+) -> String {
+    let text = item_ast.as_syntax_node().get_text(db);
+
+    if derives_store(db, attributes) || !args.derive_store {
+        return text;
+    }
+
+    // Doc comments are part of the leading trivia, which is re-emitted by `doc_comments` only.
+    let code = item_ast.as_syntax_node().get_text_without_trivia(db);
+    let attributes_code = attributes.as_syntax_node().get_text_without_trivia(db);
+    format!(
+        "{}\n{}\n{}\n",
+        doc_comments(db, &item_ast.as_syntax_node()),
+        merge_attributes(db, attributes.clone(), &[], &[STORE_DERIVE]),
+        code.strip_prefix(attributes_code.as_str())
+            .unwrap_or(&code)
+            .trim_start()
+    )
+}
+
+/// Rejects parameter lists the parser recovered from, which would be re-emitted broken.
+fn check_params(
+    db: &dyn SyntaxGroup,
+    contract_name: &str,
+    fn_ast: &ast::FunctionWithBody,
+) -> Result<(), Diagnostic> {
+    let params = fn_ast.declaration(db).signature(db).parameters(db);
+    let is_malformed = params.as_syntax_node().descendants(db).any(|node| {
+        matches!(
+            node.kind(db),
+            SyntaxKind::TokenMissing
+                | SyntaxKind::TokenSkipped
+                | SyntaxKind::ExprMissing
+                | SyntaxKind::TriviumSkippedNode
+        )
+    });
+
+    if is_malformed {
+        return Err(Diagnostic::error(format!(
+            "The parameters of '{}' in contract '{}' are malformed: '{}'.",
+            fn_ast.declaration(db).name(db).text(db),
+            contract_name,
+            params.as_syntax_node().get_text_without_trivia(db)
+        )));
+    }
+
+    Ok(())
+}
+
+/// Names and types of the parameters of a function, `self` excluded.
+fn fn_params(db: &dyn SyntaxGroup, fn_ast: &ast::FunctionWithBody) -> Vec<(String, String)> {
+    fn_ast
+        .declaration(db)
+        .signature(db)
+        .parameters(db)
+        .elements(db)
+        .iter()
+        .filter(|p| p.name(db).text(db) != "self")
+        .map(|p| {
+            (
+                p.name(db).text(db).to_string(),
+                match p.type_clause(db) {
+                    ast::OptionTypeClause::TypeClause(clause) => {
+                        clause.ty(db).as_syntax_node().get_text_without_trivia(db)
+                    }
+                    ast::OptionTypeClause::Empty(_) => String::new(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Call of the unchecked init, made by both `dojo_init` and the constructor under
+/// `init_from_constructor`.
+fn init_call(args: &ContractArgs, init_params: &[(String, String)]) -> Option<String> {
+    args.init_from_constructor.then(|| {
+        format!(
+            "self.{}({});",
+            DOJO_INIT_INTERNAL_FN,
+            init_params
+                .iter()
+                .map(|(param, _)| param.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })
+}
+
+/// Whether the function body ends with a `return;` without value.
+fn ends_with_bare_return(db: &dyn SyntaxGroup, fn_ast: &ast::FunctionWithBody) -> bool {
+    matches!(
+        fn_ast.body(db).statements(db).elements(db).last(),
+        Some(ast::Statement::Return(stmt))
+            if matches!(stmt.expr_clause(db), ast::OptionExprClause::Empty(_))
+    )
+}
+
+/// Names imported by the `use` items of the body which no other item refers to.
+///
+/// Traits are mostly used through their methods, so names looking like traits, as with
+/// [`IMPLICIT_IMPORT_SUFFIXES`], are never reported.
+fn unused_imports(db: &dyn SyntaxGroup, items: &[ModuleItem]) -> Vec<String> {
+    let mut imported = vec![];
+    let mut used = HashSet::new();
+    for item in items {
+        match item {
+            ModuleItem::Use(use_ast) => {
+                imported_names(db, &use_ast.use_path(db), None, &mut imported)
+            }
+            _ => used.extend(
+                item.as_syntax_node()
+                    .descendants(db)
+                    .filter(|node| node.kind(db) == SyntaxKind::TokenIdentifier)
+                    .map(|node| node.get_text_without_trivia(db)),
+            ),
+        }
+    }
+
+    imported
+        .into_iter()
+        .filter(|name| {
+            !used.contains(name)
+                && !IMPLICIT_IMPORT_SUFFIXES
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix))
+        })
+        .collect()
+}
+
+/// Collects the names a `use` path brings in scope, being the alias of each leaf if any.
+fn imported_names(
+    db: &dyn SyntaxGroup,
+    path: &ast::UsePath,
+    parent: Option<String>,
+    names: &mut Vec<String>,
+) {
+    match path {
+        ast::UsePath::Leaf(leaf) => {
+            let name = match leaf.alias_clause(db) {
+                ast::OptionAliasClause::AliasClause(alias) => {
+                    Some(alias.alias(db).text(db).to_string())
+                }
+                ast::OptionAliasClause::Empty(_) => {
+                    let ident = leaf.ident(db).identifier_ast(db).text(db).to_string();
+                    if ident == "self" {
+                        parent
+                    } else {
+                        Some(ident)
+                    }
+                }
+            };
+            names.extend(name);
+        }
+        ast::UsePath::Single(single) => {
+            let ident = single.ident(db).identifier_ast(db).text(db).to_string();
+            imported_names(db, &single.use_path(db), Some(ident), names);
+        }
+        ast::UsePath::Multi(multi) => {
+            for path in multi.use_paths(db).elements(db) {
+                imported_names(db, &path, parent.clone(), names);
+            }
+        }
+    }
+}
+
+/// Whether the function body panics with one of the [`TODO_PATTERNS`], whatever the spacing.
+fn is_unimplemented(db: &dyn SyntaxGroup, fn_ast: &ast::FunctionWithBody) -> bool {
+    let body: String = fn_ast
+        .body(db)
+        .as_syntax_node()
+        .get_text(db)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    TODO_PATTERNS.iter().any(|p| body.contains(p))
+}
+
+/// Whether the constructor body calls the world provider initializer, whatever the spacing.
+fn calls_initializer(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    fn_ast: &ast::FunctionWithBody,
+) -> bool {
+    let body: String = fn_ast
+        .body(db)
+        .as_syntax_node()
+        .get_text(db)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    body.contains(&format!("world_provider.{}(", args.initializer()))
+}
+
+fn missing_initializer_warning(args: &ContractArgs, name: &str) -> Diagnostic {
+    Diagnostic::warn(format!(
+        "The constructor of contract '{}' never calls 'self.world_provider.{}()', but 'skip_initializer' is set.",
+        name,
+        args.initializer()
+    ))
+}
+
+fn process_constructor(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    init_params: &[(String, String)],
+    fn_ast: &ast::FunctionWithBody,
+) -> Vec<String> {
+    let declaration = fn_ast.declaration(db);
+    let params = declaration
+        .signature(db)
+        .parameters(db)
+        .elements(db)
+        .iter()
+        .map(|p| p.as_syntax_node().get_text(db))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut nodes = vec![format!(
+        "
+            {}
+            #[constructor]
+            fn constructor({}) {{
+                {}
+                {}
+            ",
+        doc_comments(db, &fn_ast.as_syntax_node()),
+        params,
+        initializer_call(args).unwrap_or_default(),
+        init_call(args, init_params).unwrap_or_default()
+    )];
+
+    // Add function body statements
+    for stmt in fn_ast.body(db).statements(db).elements(db) {
+        nodes.push(stmt.as_syntax_node().get_text(db));
+    }
+
+    nodes.push("}".to_string());
+    nodes
+}
+
+/// Header of the init impl, up to its opening brace, and the attributes of the init function.
+///
+/// Depending on the world version, the init entrypoint is either an `#[external(v0)]` function
+/// of a per-item impl, or embedded with `#[abi(embed_v0)]` through a generated interface.
+/// The trait of a per-item impl is generated, unless `explicit_init_trait` is set.
+fn init_abi(args: &ContractArgs, signature: &str) -> (String, &'static str) {
+    if args.world_version.embeds_init() {
+        (
+            format!(
+                "#[starknet::interface]
+pub trait {trait_name}<TContractState> {{
+    {signature}
+}}
+
+#[abi(embed_v0)]
+pub impl {impl_name} of {trait_name}<{state_type}> {{",
+                trait_name = DOJO_INIT_TRAIT,
+                impl_name = DOJO_INIT_IMPL,
+                state_type = args.state_type(),
+                signature = signature
+            ),
+            "",
+        )
+    } else if args.explicit_init_trait {
+        (
+            format!(
+                "pub trait {trait_name}<TContractState> {{
+    {signature}
+}}
+
+#[abi(per_item)]
+pub impl {impl_name} of {trait_name}<{state_type}> {{",
+                trait_name = DOJO_INIT_TRAIT,
+                impl_name = DOJO_INIT_IMPL,
+                state_type = args.state_type(),
+                signature = signature
+            ),
+            "#[external(v0)]",
+        )
+    } else {
+        (
+            format!(
+                "#[abi(per_item)]\n#[generate_trait]\npub impl {} of {} {{",
+                DOJO_INIT_IMPL, DOJO_INIT_TRAIT
+            ),
+            "#[external(v0)]",
+        )
+    }
+}
+
+/// Check that `dojo_init` is called by the world, unless the init is open. The caller is
+/// either the direct one or the transaction origin, as selected by the init check.
+fn init_caller_check(args: &ContractArgs) -> String {
+    if args.open_init {
+        return String::new();
+    }
+
+    let (binding, caller, address) = match args.init_check {
+        InitCheck::Caller => ("", "caller", "starknet::get_caller_address()"),
+        InitCheck::Origin => (
+            "let origin = starknet::get_tx_info().unbox().account_contract_address;\n        ",
+            "origin",
+            "origin",
+        ),
+    };
+
+    format!(
+        "{binding}if {address} != {dispatcher}.contract_address {{
+            core::panics::panic_with_byte_array(@format!(
+                \"Only the world can init contract `{{}}`, but {caller} is `{{:?}}`\",
+                self.dojo_name(),
+                {address}
+            ));
+        }}",
+        dispatcher = WORLD_DISPATCHER
+    )
+}
+
+/// Assertion checked by `dojo_init` right after the caller check, if any.
+fn init_assert(args: &ContractArgs) -> String {
+    let Some(expr) = &args.init_assert else {
+        return String::new();
+    };
+
+    let message = expr
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('{', "{{")
+        .replace('}', "}}");
+
+    format!("assert!({}, \"Init assertion failed: {}\");", expr, message)
+}
+
+/// Best-effort check that the `dojo_init` parameters, `self` aside, can be deserialized from
+/// the entrypoint calldata. Only types which are never serializable are rejected.
+fn check_init_params(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    contract_name: &str,
+    fn_ast: &ast::FunctionWithBody,
+) -> Result<(), Diagnostic> {
+    let params = fn_ast
+        .declaration(db)
+        .signature(db)
+        .parameters(db)
+        .elements(db);
+
+    for param in params.iter().filter(|p| p.name(db).text(db) != "self") {
+        let param_name = param.name(db).text(db);
+        let is_ref = param
+            .modifiers(db)
+            .elements(db)
+            .iter()
+            .any(|m| matches!(m, ast::Modifier::Ref(_)));
+        let ty = match param.type_clause(db) {
+            ast::OptionTypeClause::TypeClause(clause) => {
+                clause.ty(db).as_syntax_node().get_text_without_trivia(db)
+            }
+            ast::OptionTypeClause::Empty(_) => String::new(),
+        };
+
+        let base_ty = ty.split('<').next().unwrap_or_default();
+        let base_ty = base_ty.rsplit("::").next().unwrap_or_default().trim();
+
+        let reason = if is_ref {
+            Some("cannot be passed by reference")
+        } else if ty.starts_with('@') {
+            Some("cannot be a snapshot")
+        } else if base_ty == args.state_type() || NON_SERIALIZABLE_TYPES.contains(&base_ty) {
+            Some("has a type which is not serializable")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            return Err(Diagnostic::error(format!(
+                "The parameter '{}' of '{}' in contract '{}' {}, as it is given by the entrypoint calldata.",
+                param_name, DOJO_INIT_FN, contract_name, reason
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn process_init(
+    db: &dyn SyntaxGroup,
+    args: &ContractArgs,
+    fn_ast: &ast::FunctionWithBody,
+) -> Vec<String> {
+    let declaration = fn_ast.declaration(db);
+    let params = declaration
+        .signature(db)
+        .parameters(db)
+        .elements(db)
+        .iter()
+        .map(|p| p.as_syntax_node().get_text(db))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let (init_header, init_fn_attrs) = init_abi(args, &interface_signature(db, args, fn_ast));
+
+    let mut nodes = vec![
+        init_header,
+        doc_comments(db, &fn_ast.as_syntax_node()),
+        init_fn_attrs.to_string(),
+        format!("fn {}({}) {{", DOJO_INIT_FN, params),
+        init_caller_check(args),
+    ];
+
+    let statements = fn_ast
+        .body(db)
+        .statements(db)
+        .elements(db)
+        .iter()
+        .map(|stmt| stmt.as_syntax_node().get_text(db))
+        .collect::<Vec<_>>();
+
+    if args.init_from_constructor {
+        nodes.extend(init_call(args, &fn_params(db, fn_ast)));
+        nodes.push("}".to_string());
+        nodes.push("}".to_string());
+        nodes.push(init_internal(args, &params, &statements));
+    } else {
+        nodes.push(init_assert(args));
+        nodes.extend(statements);
+        nodes.push("}".to_string());
+        nodes.push("}".to_string());
+    }
+
+    nodes
+}
+
+/// Impl of the unchecked init under `init_from_constructor`, holding the init assertion and
+/// body, which the constructor runs without going through the caller check of `dojo_init`.
+fn init_internal(args: &ContractArgs, params: &str, statements: &[String]) -> String {
+    format!(
+        "
+        #[generate_trait]
+        impl {} of {} {{
+            fn {}({}) {{
+                {}
+                {}
+            }}
+        }}
+        ",
+        DOJO_INIT_INTERNAL_IMPL,
+        DOJO_INIT_INTERNAL_TRAIT,
+        DOJO_INIT_INTERNAL_FN,
+        params,
+        init_assert(args),
+        statements.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{messages, parses};
+
+    /// Expanded code and diagnostic messages of a contract.
+    fn expansion(attr: &str, input: &str) -> (String, Vec<String>) {
+        let result = expand_contract_with_args(attr, input);
+        (result.token_stream.to_string(), messages(&result))
+    }
+
+    #[test]
+    fn generated_code_uses_the_configured_indent() {
+        let (code, diagnostics) = expansion(
+            "indent: 2",
+            "mod actions {
+                fn spawn(ref self: ContractState) {
+                    let x = 1;
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(crate::utils::parses(&code));
+        assert!(code.contains("\n  fn constructor(ref self: ContractState) {\n    self.world_provider.initializer();\n"));
+        assert!(code.contains("\n  fn spawn(ref self: ContractState) {\n    let x = 1;\n  }\n"));
+        assert!(code
+            .lines()
+            .all(|line| (line.len() - line.trim_start().len()) % 2 == 0));
+
+        let (_, diagnostics) = expansion("indent: 0", "mod actions {}");
+        assert_eq!(
+            diagnostics,
+            ["Unsupported indent width '0', expected between 1 and 8."]
+        );
+    }
+
+    #[test]
+    fn generated_code_is_indented_with_4_spaces_by_default() {
+        let input = "mod actions {
+  fn spawn(ref self: ContractState) {
+      let x = 1;
+  }
+}";
+        let (code, diagnostics) = expansion("", input);
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(
+            code.contains("\n    fn spawn(ref self: ContractState) {\n        let x = 1;\n    }\n")
+        );
+        assert!(code
+            .lines()
+            .all(|line| (line.len() - line.trim_start().len()) % 4 == 0));
+        assert_eq!(code, expansion("indent: 4", input).0);
+    }
+
+    #[test]
+    fn attributed_variants_of_storage_enums_survive() {
+        let status = "#[derive(Drop, Serde, Copy, starknet::Store)]
+    enum Status {
+        /// Not started yet.
+        #[default]
+        Idle,
+        #[allow(unused)]
+        Playing: u32,
+    }";
+        let input = format!(
+            "mod actions {{
+    use starknet::storage::Map;
+
+    {}
+
+    #[storage]
+    struct Storage {{
+        games: Map<felt252, Status>,
+    }}
+}}",
+            status
+        );
+
+        for attr in ["", "derive_store"] {
+            let (code, diagnostics) = expansion(attr, &input);
+
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert!(code.contains(status), "{}", code);
+        }
+
+        // Derived by the macro, the variants are kept along with the rest of the enum.
+        let (code, diagnostics) =
+            expansion("derive_store", &input.replace(", starknet::Store)]", ")]"));
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(
+            code.contains(&status[status.find("enum Status").unwrap()..]),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn storage_field_named_like_the_world_provider_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[storage]
+                struct Storage {
+                    world_provider: u8,
+                }
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The storage field 'world_provider' of contract 'actions' collides with the component storage injected by the contract macro, please rename it."]
+        );
+    }
+
+    #[test]
+    fn storage_attributes_are_preserved() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[storage]
+                #[cfg_attr(feature: 'debug', derive(Debug))]
+                struct Storage {
+                    count: u32,
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("#[cfg_attr(feature: 'debug', derive(Debug))]"));
+        assert_eq!(code.matches("#[storage]").count(), 1);
+    }
+
+    #[test]
+    fn event_derives_are_merged() {
+        let (code, _) = expansion(
+            "",
+            "mod actions {
+                #[event]
+                #[derive(Drop, Debug, starknet::Event)]
+                enum Event {}
+            }",
+        );
+
+        assert_eq!(code.matches("#[event]").count(), 1);
+        assert!(code.contains("#[derive(Drop, starknet::Event, Debug)]"));
+    }
+
+    #[test]
+    fn main_function_is_rejected() {
+        let input = "mod actions {
+            fn main() {}
+        }";
+        let (code, diagnostics) = expansion("", input);
+
+        assert_eq!(
+            diagnostics,
+            ["The contract 'actions' cannot define a function named 'main', this name is reserved."]
+        );
+        assert_eq!(code, input);
+    }
+
+    #[test]
+    fn user_generate_trait_impls_pass_through() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[generate_trait]
+                impl HelpersImpl of HelpersTrait {
+                    fn helper(self: @ContractState) {}
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(code.contains("impl HelpersImpl of HelpersTrait"));
+        assert!(code.contains(&format!("impl {} of {}", DOJO_INIT_IMPL, DOJO_INIT_TRAIT)));
+    }
+
+    #[test]
+    fn user_impl_named_like_the_init_impl_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[generate_trait]
+                impl IDojoInitImpl of HelpersTrait {}
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The name 'IDojoInitImpl' in contract 'actions' collides with an item generated by the contract macro, please rename it."]
+        );
+    }
+
+    #[test]
+    fn world_helper_is_generated_only_when_set() {
+        let input = "mod actions {}";
+        let helper = "fn world(self: @ContractState) -> dojo::world::IWorldDispatcher";
+
+        assert!(expansion("world_helper", input).0.contains(helper));
+        assert!(!expansion("", input).0.contains(helper));
+    }
+
+    #[test]
+    fn world_helper_conflicts_with_a_user_world_function() {
+        let (_, diagnostics) = expansion(
+            "world_helper",
+            "mod actions {
+                fn world() {}
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The contract 'actions' already defines a 'world' function, which conflicts with the one generated by `world_helper`."]
+        );
+    }
+
+    #[test]
+    fn world_helper_requires_the_world_provider() {
+        let (_, diagnostics) = expansion(
+            "world_helper, world_provider: false, open_init",
+            "mod actions {}",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The 'world_helper' argument reads the world from the world provider, which is disabled by `world_provider = false`."]
+        );
+    }
+
+    #[test]
+    fn error_results_carry_the_original_item() {
+        for input in [
+            "struct Position {}",
+            "mod bad-name {}",
+            "mod actions { mod nested {} }",
+            "mod actions;",
+        ] {
+            let (code, diagnostics) = expansion("", input);
+            assert!(!diagnostics.is_empty(), "{}", input);
+            assert_eq!(code, input);
+        }
+
+        let input = "mod actions {}";
+        assert_eq!(expansion("unknown_flag", input).0, input);
+    }
+
+    #[test]
+    fn module_visibility_is_preserved() {
+        assert!(expansion("", "pub mod actions {}")
+            .0
+            .contains("pub mod actions {"));
+        assert!(expansion("", "pub(crate) mod actions {}")
+            .0
+            .contains("pub(crate) mod actions {"));
+        assert!(expansion("", "mod actions {}")
+            .0
+            .contains("\nmod actions {"));
+    }
+
+    #[test]
+    fn module_attrs_are_applied_to_the_module() {
+        let (code, diagnostics) =
+            expansion("module_attrs(feature(\"experimental\"))", "mod actions {}");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("#[starknet::contract]\n#[feature(\"experimental\")]\nmod actions {"));
+    }
+
+    #[test]
+    fn invalid_module_attrs_are_rejected() {
+        let (_, diagnostics) = expansion("module_attrs(1 + 2)", "mod actions {}");
+        assert_eq!(
+            diagnostics,
+            ["Invalid module attribute '1 + 2' in 'module_attrs'."]
+        );
+    }
+
+    #[test]
+    fn nested_event_variants_are_preserved() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[event]
+                #[derive(Drop, starknet::Event)]
+                enum Event {
+                    #[flat]
+                    GameEvent: GameEvent,
+                    Moved: Moved,
+                }
+
+                #[derive(Drop, starknet::Event)]
+                enum GameEvent {
+                    Started: Started,
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let start = code.find("enum Event {").unwrap();
+        let event = &code[start..start + code[start..].find('}').unwrap()];
+        let flat = event.find("#[flat]").unwrap();
+        assert!(flat < event.find("GameEvent: GameEvent,").unwrap());
+        assert!(
+            event.find("GameEvent: GameEvent,").unwrap() < event.find("Moved: Moved,").unwrap()
+        );
+        assert!(code.contains("enum GameEvent {\n        Started: Started,\n    }"));
+    }
+
+    #[test]
+    fn world_version_selects_the_init_abi() {
+        let input = "mod actions {}";
+        let (v1, _) = expansion("world_version: 1", input);
+        let (v2, diagnostics) = expansion("world_version: 2", input);
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        for code in [&v1, &v2] {
+            assert!(code.contains(WORLD_DISPATCHER));
+        }
+        assert!(v1.contains("#[abi(per_item)]\n    #[generate_trait]\n    pub impl IDojoInitImpl"));
+        assert!(
+            v2.contains("#[abi(embed_v0)]\n    pub impl IDojoInitImpl of IDojoInit<ContractState>")
+        );
+        assert!(!v2.contains("#[generate_trait]\n    pub impl IDojoInitImpl"));
+    }
+
+    #[test]
+    fn unsupported_world_version_is_rejected() {
+        assert_eq!(
+            expansion("world_version: 3", "mod actions {}").1,
+            ["Unsupported world version '3', supported versions are: 1, 2."]
+        );
+    }
+
+    #[test]
+    fn storage_field_named_like_a_component_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[storage]
+                struct Storage {
+                    upgradeable: u8,
+                }
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The storage field 'upgradeable' of contract 'actions' collides with the component storage injected by the contract macro, please rename it."]
+        );
+    }
+
+    #[test]
+    fn raw_expansion_omits_the_contract_patch() {
+        let (code, diagnostics) = expansion("raw", "mod actions {}");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(!code.contains("#[starknet::contract]"));
+        assert!(!code.contains("mod actions"));
+        assert!(code.contains("fn constructor(ref self: ContractState)"));
+        assert!(code.contains("#[storage]"));
+        assert!(code.contains("enum Event {"));
+    }
+
+    #[test]
+    fn custom_initializer_is_called_by_the_constructor() {
+        let (code, diagnostics) = expansion("initializer: \"init_world\"", "mod actions {}");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("self.world_provider.init_world();"));
+        assert!(!code.contains("self.world_provider.initializer();"));
+    }
+
+    #[test]
+    fn invalid_initializer_is_rejected() {
+        assert_eq!(
+            expansion("initializer: \"init-world\"", "mod actions {}").1,
+            ["The initializer name 'init-world' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_)."]
+        );
+    }
+
+    #[test]
+    fn trailing_semicolon_is_ignored() {
+        let (code, diagnostics) = expansion("", "mod actions {\n    fn helper() {}\n};");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert_eq!(
+            code,
+            expansion("", "mod actions {\n    fn helper() {}\n}").0
+        );
+    }
+
+    #[test]
+    fn event_referencing_a_disabled_component_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "upgradeable: false",
+            "mod actions {
+                #[event]
+                #[derive(Drop, starknet::Event)]
+                enum Event {
+                    Upgraded: upgradeable_cpt::Event,
+                }
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The event variant 'Upgraded' of contract 'actions' references the component 'upgradeable_cpt', which is disabled by `upgradeable = false`."]
+        );
+    }
+
+    #[test]
+    fn embed_of_a_component_without_storage_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "upgradeable: false",
+            "mod actions {
+                #[abi(embed_v0)]
+                impl UpgradeableImpl = upgradeable_cpt::UpgradeableImpl<ContractState>;
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The impl 'UpgradeableImpl' of contract 'actions' embeds the component 'upgradeable_cpt', but its substorage 'upgradeable' is missing from Storage."]
+        );
+    }
+
+    #[test]
+    fn embed_of_a_user_component_with_storage_is_accepted() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                component!(path: ownable_cpt, storage: ownable, event: OwnableEvent);
+
+                #[abi(embed_v0)]
+                impl OwnableImpl = ownable_cpt::OwnableImpl<ContractState>;
+
+                #[storage]
+                struct Storage {
+                    #[substorage(v0)]
+                    ownable: ownable_cpt::Storage,
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn user_trait_shadowing_the_init_trait_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                trait IDojoInit<T> {}
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The trait 'IDojoInit' in contract 'actions' collides with the trait generated by the contract macro, please rename it (e.g. 'IDojoInitExt')."]
+        );
+    }
+
+    #[test]
+    fn map_and_vec_storage_members_round_trip() {
+        let members = "/// Scores by player.
+        scores: Map<ContractAddress, u32>,
+        #[allow(unused)]
+        history: Vec<felt252>,";
+        let (code, diagnostics) = expansion(
+            "",
+            &format!(
+                "mod actions {{
+                #[storage]
+                struct Storage {{
+                    {}
+                }}
+            }}",
+                members
+            ),
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains(members), "{}", code);
+    }
+
+    #[test]
+    fn gen_interface_lists_the_external_functions() {
+        let (code, diagnostics) = expansion(
+            "gen_interface: \"IActions\"",
+            "mod actions {
+                #[external(v0)]
+                fn spawn(ref self: ContractState, x: u32) {}
+
+                #[external(v0)]
+                fn position(self: @ContractState) -> u32 {
+                    0
+                }
+
+                fn helper() {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let start = code.find("pub trait IActions<TContractState> {").unwrap();
+        let trait_code = &code[start..start + code[start..].find('}').unwrap()];
+        assert!(trait_code.contains("fn spawn(ref self: TContractState, x: u32);"));
+        assert!(trait_code.contains("fn position(self: @TContractState) -> u32;"));
+        assert!(!trait_code.contains("helper"));
+        assert!(code[..start].trim_end().ends_with("#[starknet::interface]"));
+    }
+
+    #[test]
+    fn invalid_interface_name_is_rejected() {
+        assert_eq!(
+            expansion("gen_interface: \"I-Actions\"", "mod actions {}").1,
+            ["The interface name 'I-Actions' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_)."]
+        );
+    }
+
+    #[test]
+    fn embedded_init_is_declared_by_an_interface() {
+        let (code, diagnostics) = expansion(
+            "world_version: 2",
+            "mod actions {
+                fn dojo_init(self: @ContractState, count: u32) {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains(
+            "#[starknet::interface]
+    pub trait IDojoInit<TContractState> {
+        fn dojo_init(self: @TContractState, count: u32);
+    }
+
+    #[abi(embed_v0)]
+    pub impl IDojoInitImpl of IDojoInit<ContractState> {"
+        ));
+        assert!(!code.contains("#[external(v0)]"));
+        assert!(expansion("world_version: 1", "mod actions {}")
+            .0
+            .contains("#[external(v0)]"));
+    }
+
+    #[test]
+    fn casing_warning_fires_for_non_snake_case_names() {
+        assert_eq!(
+            expansion("", "mod MyContract {}").1,
+            ["The contract name 'MyContract' should be in snake case, consider renaming it to 'my_contract'."]
+        );
+        assert!(expansion("", "mod my_contract {}").1.is_empty());
+        assert!(expansion("allow_non_snake_case", "mod MyContract {}")
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn test_world_is_gated_by_cfg_test() {
+        let input = "mod actions {}";
+        let (code, diagnostics) = expansion("test_world", input);
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let start = code.find("pub impl TestWorldImpl").unwrap();
+        assert!(code[..start]
+            .trim_end()
+            .ends_with("#[cfg(test)]\n    #[generate_trait]"));
+        assert!(code.contains(
+            "fn set_test_world(ref self: ContractState, world: dojo::world::IWorldDispatcher)"
+        ));
+        assert!(!expansion("", input).0.contains("set_test_world"));
+    }
+
+    #[test]
+    fn test_world_requires_the_world_provider() {
+        assert_eq!(
+            expansion("test_world, world_provider: false, open_init", "mod actions {}").1,
+            ["The 'test_world' argument writes the world to the world provider storage, which is disabled by `world_provider = false`."]
+        );
+    }
+
+    #[test]
+    fn init_assert_follows_the_caller_check() {
+        let (code, diagnostics) = expansion(
+            "init_assert: \"count > 0\"",
+            "mod actions {
+                fn dojo_init(self: @ContractState, count: u32) {
+                    let _count = count;
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let check = code.find("starknet::get_caller_address()").unwrap();
+        let assertion = code
+            .find("assert!(count > 0, \"Init assertion failed: count > 0\");")
+            .unwrap();
+        let body = code.find("let _count = count;").unwrap();
+        assert!(check < assertion && assertion < body);
+    }
+
+    #[test]
+    fn invalid_init_assert_is_rejected() {
+        assert_eq!(
+            expansion("init_assert: \"count >\"", "mod actions {}").1,
+            ["Invalid expression 'count >' in 'init_assert'."]
+        );
+    }
+
+    #[test]
+    fn empty_event_enum_gets_the_component_variants() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[event]
+                #[derive(Drop, starknet::Event)]
+                enum Event {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        let start = code.find("enum Event {").unwrap();
+        let event = &code[start..start + code[start..].find('}').unwrap()];
+        assert!(event.contains("UpgradeableEvent: upgradeable_cpt::Event,"));
+        assert!(event.contains("WorldProviderEvent: world_provider_cpt::Event,"));
+        assert!(!event.contains(",,"));
+    }
+
+    #[test]
+    fn namespace_argument_propagates_to_the_output() {
+        let (code, diagnostics) = expansion("namespace: \"game\"", "mod actions {}");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("\"game-actions\""));
+        assert!(code.contains("self.world(@\"game\")"));
+        assert_eq!(
+            expansion("", "mod actions {}").0,
+            expand_contract("mod actions {}").token_stream.to_string()
+        );
+    }
+
+    #[test]
+    fn disabled_world_provider_is_never_referenced() {
+        for attr in [
+            "world_provider: false, open_init",
+            "world_provider: false, open_init, namespace: \"game\"",
+        ] {
+            let (code, diagnostics) = expansion(attr, "mod actions {}");
+
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert!(parses(&code));
+            assert!(!code.contains("world_provider"), "{}", code);
+            assert!(code.contains("fn constructor(ref self: ContractState) {"));
+        }
+    }
+
+    #[test]
+    fn world_storage_is_built_from_the_world_provider() {
+        let (code, _) = expansion("namespace: \"game\"", "mod actions {}");
+        assert!(code.contains(
+            "fn world(self: @ContractState, namespace: @ByteArray) -> dojo::world::storage::WorldStorage {
+            dojo::world::WorldStorageTrait::new(self.world_provider.world_dispatcher(), namespace)
+        }"
+        ));
+        assert!(code.contains("fn world_default(self: @ContractState)"));
+    }
+
+    #[test]
+    fn constructor_returning_a_value_is_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[constructor]
+                fn constructor(ref self: ContractState) -> u32 {
+                    1
+                }
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The constructor of contract 'actions' cannot return a value, but returns 'u32'."]
+        );
+    }
+
+    #[test]
+    fn constructor_returning_unit_is_accepted() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[constructor]
+                fn constructor(ref self: ContractState) -> () {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+    }
+
+    #[test]
+    fn constructor_and_init_docs_are_kept() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                /// Sets the counter up.
+                #[constructor]
+                fn constructor(ref self: ContractState) {}
+
+                /// Starts the game.
+                /// Once.
+                fn dojo_init(self: @ContractState) {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let constructor = code.find("fn constructor(").unwrap();
+        let init = code.find("fn dojo_init(").unwrap();
+        let docs = |at: usize, doc: &str| {
+            code[..at]
+                .rfind(doc)
+                .is_some_and(|d| !code[d..at].contains("fn "))
+        };
+        assert!(docs(constructor, "/// Sets the counter up."));
+        assert!(docs(init, "/// Starts the game.\n        /// Once."));
+    }
+
+    #[test]
+    fn non_serializable_init_params_are_rejected() {
+        let init = |params: &str| {
+            expansion(
+                "",
+                &format!(
+                    "mod actions {{ fn dojo_init(self: @ContractState, {}) {{}} }}",
+                    params
+                ),
+            )
+            .1
+        };
+
+        assert_eq!(
+            init("ref count: u32"),
+            ["The parameter 'count' of 'dojo_init' in contract 'actions' cannot be passed by reference, as it is given by the entrypoint calldata."]
+        );
+        assert_eq!(
+            init("names: @Array<felt252>"),
+            ["The parameter 'names' of 'dojo_init' in contract 'actions' cannot be a snapshot, as it is given by the entrypoint calldata."]
+        );
+        assert_eq!(
+            init("scores: core::dict::Felt252Dict<u8>"),
+            ["The parameter 'scores' of 'dojo_init' in contract 'actions' has a type which is not serializable, as it is given by the entrypoint calldata."]
+        );
+        assert!(init("names: Array<felt252>, owner: ContractAddress").is_empty());
+    }
+
+    #[test]
+    fn contracts_expand_with_programmatic_args() {
+        let args = ContractArgs {
+            namespace: Some("game".to_string()),
+            ..ContractArgs::default()
+        };
+
+        assert_eq!(
+            expand_contract_with(&args, "mod actions {}")
+                .token_stream
+                .to_string(),
+            expansion("namespace: \"game\"", "mod actions {}").0
+        );
+    }
+
+    /// Code between each pair of generated markers.
+    fn generated_sections(code: &str) -> Vec<&str> {
+        code.split(GENERATED_START_MARKER)
+            .skip(1)
+            .map(|section| section.split_once(GENERATED_END_MARKER).unwrap().0)
+            .collect()
+    }
+
+    #[test]
+    fn markers_bracket_the_injected_defaults() {
+        let (code, _) = expansion("", "mod actions {}");
+        let sections = generated_sections(&code);
+
+        assert_eq!(sections.len(), 2);
+        assert!(sections[0].contains("component!(path: "));
+        assert!(sections[0].contains("storage: upgradeable"));
+        for item in [
+            "struct Storage {",
+            "enum Event {",
+            "fn constructor(",
+            "fn dojo_init(",
+        ] {
+            assert!(sections[1].contains(item), "{}", item);
+        }
+    }
+
+    #[test]
+    fn markers_bracket_the_members_injected_into_user_items() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                #[storage]
+                struct Storage {
+                    count: u32,
+                }
+
+                #[event]
+                #[derive(Drop, starknet::Event)]
+                enum Event {
+                    Moved: Moved,
+                }
+
+                #[constructor]
+                fn constructor(ref self: ContractState) {}
+
+                fn dojo_init(self: @ContractState) {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        let sections = generated_sections(&code);
+
+        assert_eq!(sections.len(), 3);
+        assert!(sections[0].contains("component!("));
+        assert!(sections[1].contains("#[substorage(v0)]\n        world_provider: "));
+        assert!(!sections[1].contains("count"));
+        assert!(sections[2].contains("WorldProviderEvent"));
+        assert!(!sections[2].contains("Moved"));
+    }
+
+    #[test]
+    fn use_groups_are_kept_verbatim() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                use foo::{a, b as c};
+                use bar::{d::{e, f}, g};
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(code.contains("use foo::{a, b as c};"));
+        assert!(code.contains("use bar::{d::{e, f}, g};"));
+    }
+
+    #[test]
+    fn skipped_initializer_must_be_called_by_the_constructor() {
+        let constructor = |body: &str| {
+            expansion(
+                "skip_initializer",
+                &format!(
+                    "mod actions {{ #[constructor] fn constructor(ref self: ContractState) {{ {} }} }}",
+                    body
+                ),
+            )
+        };
+
+        let (code, diagnostics) = constructor("self.world_provider . initializer();");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(code.matches("initializer()").count(), 1);
+
+        let (_, diagnostics) = constructor("");
+        assert_eq!(
+            diagnostics,
+            ["The constructor of contract 'actions' never calls 'self.world_provider.initializer()', but 'skip_initializer' is set."]
+        );
+    }
+
+    #[test]
+    fn injected_items_use_the_configured_state_type() {
+        let (code, diagnostics) = expansion(
+            "state_type: \"GameState\", namespace: \"game\", world_helper",
+            "mod actions {}",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("fn constructor(ref self: GameState)"));
+        assert!(code.contains("fn dojo_init(self: @GameState)"));
+        assert!(code.contains("fn world_default(self: @GameState)"));
+        assert!(code.contains("IContract<GameState>"));
+        assert!(
+            !code.replace("TContractState", "").contains("ContractState"),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn starknet_module_attributes_are_rejected() {
+        for attr in ["starknet::contract", "starknet::interface"] {
+            let (_, diagnostics) = expansion("", &format!("#[{}]\nmod actions {{}}", attr));
+
+            assert_eq!(
+                diagnostics,
+                [format!("The contract module 'actions' cannot be annotated with '#[{}]', as the contract macro already applies '#[starknet::contract]'.", attr)]
+            );
+        }
+    }
+
+    #[test]
+    fn substorage_components_embed_their_mapped_impl() {
+        let input = "mod actions {
+                #[storage]
+                struct Storage {
+                    #[substorage(v0)]
+                    ownable_state: openzeppelin::access::ownable::OwnableComponent::Storage,
+                }
+            }";
+        let (code, diagnostics) = expansion(
+            "auto_components(openzeppelin::access::ownable::OwnableComponent = OwnableImpl)",
+            input,
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(code.contains(
+            "component!(path: openzeppelin::access::ownable::OwnableComponent, storage: ownable_state, event: OwnableStateEvent);
+
+    #[abi(embed_v0)]
+    impl OwnableImpl = openzeppelin::access::ownable::OwnableComponent::OwnableImpl<ContractState>;"
+        ));
+        assert!(code.contains(
+            "OwnableStateEvent: openzeppelin::access::ownable::OwnableComponent::Event,"
+        ));
+
+        assert_eq!(
+            expansion("auto_components(OwnableComponent = OwnableImpl)", input).1,
+            ["The component 'OwnableComponent' of 'auto_components' has no #[substorage(v0)] field in the Storage of contract 'actions'."]
+        );
+    }
+
+    /// Deterministic mutations of `source`: chars removed, duplicated or replaced by Cairo
+    /// punctuation, picked by a xorshift generator so failures can be replayed.
+    #[cfg(feature = "fuzz")]
+    fn mutations(source: &str, seed: u64, count: usize) -> Vec<String> {
+        const NOISE: &[char] = &[
+            '{', '}', '(', ')', '<', '>', ':', ';', ',', '#', '[', ']', '"', '@', ' ', '\n', 'é',
+        ];
+        let mut state = seed | 1;
+        let mut next = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        (0..count)
+            .map(|_| {
+                let mut chars = source.chars().collect::<Vec<_>>();
+                for _ in 0..1 + next(4) {
+                    let at = next(chars.len() + 1).min(chars.len().saturating_sub(1));
+                    match next(3) {
+                        0 if !chars.is_empty() => {
+                            chars.remove(at);
+                        }
+                        1 if !chars.is_empty() => chars.insert(at, chars[at]),
+                        _ => chars.insert(at, NOISE[next(NOISE.len())]),
+                    }
+                }
+                chars.into_iter().collect()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "fuzz")]
+    #[test]
+    fn mutated_contracts_never_panic() {
+        let sources = [
+            "mod actions {
+                #[storage]
+                struct Storage { count: u32, #[substorage(v0)] ownable: ownable_cpt::Storage }
+
+                #[event]
+                #[derive(Drop, starknet::Event)]
+                enum Event { Moved: Moved }
+
+                #[constructor]
+                fn constructor(ref self: ContractState, owner: ContractAddress) {}
+
+                fn dojo_init(self: @ContractState, count: u32) {}
+
+                #[abi(embed_v0)]
+                impl ActionsImpl of IActions<ContractState> {
+                    fn spawn(ref self: ContractState) -> u32 { 1 }
+                }
+
+                use foo::{a, b as c};
+            }",
+            "mod m {}",
+        ];
+        let attrs = [
+            "",
+            "namespace: \"game\", world_helper, gen_interface: \"IActions\"",
+            "auto_components, emit_helpers, init_from_constructor, open_init",
+        ];
+
+        for (seed, source) in sources.iter().enumerate() {
+            for input in mutations(source, seed as u64 + 1, 200) {
+                for attr in attrs.iter().map(|a| a.to_string()).chain(mutations(
+                    attrs[1],
+                    seed as u64 + 7,
+                    2,
+                )) {
+                    let result =
+                        std::panic::catch_unwind(|| expand_contract_with_args(&attr, &input));
+                    assert!(
+                        result.is_ok(),
+                        "panicked on attr {:?} and input:\n{}",
+                        attr,
+                        input
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn args_given_with_the_macro_path_are_parsed() {
+        let (expected, _) = expansion("namespace: \"game\"", "mod actions {}");
+
+        for attr in [
+            "dojo::contract(namespace: \"game\")",
+            "contract(namespace: \"game\")",
+            "#[dojo::contract(namespace: \"game\")]",
+        ] {
+            let (code, diagnostics) = expansion(attr, "mod actions {}");
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert_eq!(code, expected, "{}", attr);
+        }
+        assert!(expected.contains("fn world_default("));
+    }
+
+    #[test]
+    fn dojo_name_is_qualified_by_the_namespace() {
+        let dojo_name = |attr: &str| {
+            let (code, _) = expansion(attr, "mod actions {}");
+            let start = code.find("fn dojo_name(").unwrap();
+            let body = &code[start..];
+            body[body.find('"').unwrap()..][1..]
+                .split('"')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(dojo_name("namespace: \"game\""), "game-actions");
+        assert_eq!(dojo_name(""), "actions");
+    }
+
+    #[test]
+    fn cfg_gates_the_whole_module() {
+        let (code, diagnostics) = expansion("cfg: \"feature: \\\"game\\\"\"", "mod actions {}");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(
+            code.starts_with("#[cfg(feature: \"game\")]\n#[starknet::contract]"),
+            "{}",
+            code
+        );
+
+        let (_, diagnostics) = expansion("cfg: \"feature(\"", "mod actions {}");
+        assert_eq!(diagnostics, ["Invalid predicate 'feature(' in 'cfg'."]);
+    }
+
+    #[test]
+    fn storage_world_reads_the_dispatcher_field() {
+        let (code, diagnostics) = expansion(
+            "storage_world",
+            "mod actions {
+                #[storage]
+                struct Storage {
+                    count: u32,
+                    cached_world: dojo::world::IWorldDispatcher,
+                }
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(code.contains(
+            "fn world(self: @ContractState) -> dojo::world::IWorldDispatcher {
+        self.cached_world.read()
+    }"
+        ));
+
+        let (code, diagnostics) = expansion("storage_world", "mod actions {}");
+        assert!(!code.contains("-> dojo::world::IWorldDispatcher {"));
+        assert_eq!(
+            diagnostics,
+            ["The contract 'actions' sets `storage_world`, but has no storage field of type 'IWorldDispatcher'."]
+        );
+    }
+
+    #[test]
+    fn generic_entrypoints_are_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions { fn dojo_init<T, +Drop<T>>(self: @ContractState, value: T) {} }",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The 'dojo_init' entrypoint of contract 'actions' cannot be generic, but declares '<T, +Drop<T>>'."]
+        );
+
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions { #[constructor] fn constructor<T>(ref self: ContractState) {} }",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The 'constructor' entrypoint of contract 'actions' cannot be generic, but declares '<T>'."]
+        );
+    }
+
+    #[test]
+    fn account_flavor_is_applied() {
+        let (code, diagnostics) = expansion(
+            "account",
+            "mod wallet {
+                #[constructor]
+                fn constructor(ref self: ContractState, key: felt252) {}
+
+                fn dojo_init(self: @ContractState) {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(
+            code.starts_with("#[starknet::contract(account)]"),
+            "{}",
+            code
+        );
+        assert!(code.contains("fn constructor(ref self: ContractState, key: felt252) {"));
+        assert!(code.contains("fn dojo_init(self: @ContractState) {"));
+
+        let (code, _) = expansion("", "mod wallet {}");
+        assert!(code.starts_with("#[starknet::contract]"));
+    }
+
+    #[test]
+    fn literals_survive_the_expansion() {
+        let body = r#"let text: ByteArray = "a\nb \"quoted\" \\";
+        let short = 'short';
+        let hex = 0x1f_u8;
+        let placeholder: ByteArray = "$name$ $body$";"#;
+        let (code, diagnostics) = expansion(
+            "",
+            &format!(
+                "mod actions {{ fn literals() {{\n                {}\n            }} }}",
+                body
+            ),
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains(body), "{}", code);
+    }
+
+    #[test]
+    fn init_caller_check_goes_through_the_world_provider() {
+        let (code, _) = expansion("", "mod actions { fn dojo_init(self: @ContractState) {} }");
+        let init = &code[code.find("fn dojo_init(").unwrap()..];
+
+        assert!(
+            init.contains("self.world_provider.world_dispatcher()"),
+            "{}",
+            init
+        );
+        assert!(code.contains("impl WorldProviderImpl = world_provider_cpt::WorldProviderImpl<"));
+    }
+
+    #[test]
+    fn no_event_skips_the_event_enum() {
+        let (code, diagnostics) = expansion(
+            "no_event, upgradeable: false, world_provider: false, open_init",
+            "mod actions {}",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(!code.contains("enum Event"));
+
+        let (_, diagnostics) = expansion("no_event", "mod actions {}");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]
+            .starts_with("The contract 'actions' sets `no_event`, but the components "));
+
+        let (_, diagnostics) = expansion(
+            "no_event, upgradeable: false, world_provider: false, open_init",
+            "mod actions { #[event] #[derive(Drop, starknet::Event)] enum Event {} }",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The contract 'actions' sets `no_event`, but defines an 'Event' enum."]
+        );
+    }
+
+    #[test]
+    fn expanded_contracts_are_not_expanded_again() {
+        let (expanded, _) = expansion("", "mod actions {}");
+        // Without its `#[starknet::contract]` attribute, which is rejected on its own.
+        let module = &expanded[expanded.find("mod actions").unwrap()..];
+        let (code, diagnostics) = expansion("", module);
+
+        assert_eq!(
+            diagnostics,
+            ["The contract module 'actions' is already expanded by the contract macro, which must only be applied once."]
+        );
+        assert_eq!(code, module);
+    }
+
+    #[test]
+    fn only_body_level_markers_flag_an_expansion() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {\n    // --- dojo generated ---\n    fn spawn() {}\n}",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The contract module 'actions' is already expanded by the contract macro, which must only be applied once."]
+        );
+
+        for input in [
+            "mod actions { fn spawn() { let s: ByteArray = \"// --- dojo generated ---\"; } }",
+            "mod actions {\n    fn spawn() {\n        // --- dojo generated ---\n    }\n}",
+            "mod actions {\n    // --- dojo generated --- by hand\n    fn spawn() {}\n}",
+        ] {
+            let (_, diagnostics) = expansion("", input);
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        }
+    }
+
+    #[test]
+    fn unimplemented_entrypoints_are_reported() {
+        let input = "mod actions {
+            #[abi(embed_v0)]
+            impl ActionsImpl of IActions<ContractState> {
+                fn spawn(ref self: ContractState) {
+                    panic!(\"unimplemented\");
+                }
+
+                fn play(ref self: ContractState) {}
+            }
+
+            #[external(v0)]
+            fn move(ref self: ContractState) {
+                core::panic_with_felt252('TODO');
+            }
+        }";
+
+        let (_, diagnostics) = expansion("report_todos", input);
+        assert_eq!(
+            diagnostics,
+            ["The contract 'actions' has unimplemented entrypoints: 'spawn', 'move'."]
+        );
+        assert!(expansion("", input).1.is_empty());
+    }
+
+    #[test]
+    fn components_sharing_a_substorage_field_are_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "mod actions {
+                component!(path: ownable_cpt, storage: upgradeable, event: OwnableEvent);
+            }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The components 'upgradeable_cpt' and 'ownable_cpt' of contract 'actions' use the same substorage field 'upgradeable', please rename one of them."]
+        );
+    }
+
+    #[test]
+    fn explicit_init_trait_keeps_the_caller_check() {
+        for input in [
+            "mod actions {}",
+            "mod actions { fn dojo_init(self: @ContractState) {} }",
+        ] {
+            let (code, diagnostics) = expansion("explicit_init_trait", input);
+
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert!(parses(&code));
+            assert!(
+                code.contains(
+                    "pub trait IDojoInit<TContractState> {
+        fn dojo_init(self: @TContractState);
+    }
+
+    #[abi(per_item)]
+    pub impl IDojoInitImpl of IDojoInit<ContractState> {"
+                ),
+                "{}",
+                code
+            );
+            let init = &code[code.find("fn dojo_init(self: @ContractState)").unwrap()..];
+            assert!(init.contains("Only the world can init contract"));
+        }
+    }
+
+    #[test]
+    fn entrypoints_keep_their_source_order() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+                fn dojo_init(self: @ContractState) {}
+
+                #[constructor]
+                fn constructor(ref self: ContractState) {}
+            }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.find("fn dojo_init(").unwrap() < code.find("fn constructor(").unwrap());
+    }
+
+    #[test]
+    fn expansions_are_byte_stable() {
+        let attr = "namespace: \"game\", auto_components, emit_helpers, gen_interface: \"IActions\", warn_unused_imports";
+        let input = "mod actions {
+            use foo::{a, b};
+
+            #[storage]
+            struct Storage {
+                #[substorage(v0)]
+                ownable: ownable_cpt::Storage,
+                #[substorage(v0)]
+                pausable: pausable_cpt::Storage,
+            }
+
+            #[external(v0)]
+            fn spawn(ref self: ContractState) {}
+
+            #[external(v0)]
+            fn play(ref self: ContractState) {}
+        }";
+
+        let (first, first_diagnostics) = expansion(attr, input);
+        let (second, second_diagnostics) = expansion(attr, input);
+
+        assert_eq!(first, second);
+        assert_eq!(first_diagnostics, second_diagnostics);
+    }
+
+    #[test]
+    fn validation_reports_the_expansion_diagnostics() {
+        let cases = [
+            ("", "mod actions { mod nested {} }"),
+            ("", "mod actions { fn main() {} }"),
+            ("", "struct actions {}"),
+            ("colour", "mod actions {}"),
+            (
+                "report_todos, warn_unused_imports, storage_world",
+                "mod Actions {
+                    use foo::bar;
+
+                    #[external(v0)]
+                    fn spawn(ref self: ContractState) {
+                        panic!(\"unimplemented\");
+                    }
+                }",
+            ),
+            ("", "mod actions {}"),
+        ];
+
+        for (attr, input) in cases {
+            let (_, expected) = expansion(attr, input);
+            let diagnostics = validate_contract(attr, input)
+                .into_iter()
+                .map(|d| d.message)
+                .collect::<Vec<_>>();
+
+            assert_eq!(diagnostics, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn component_imports_are_injected_by_default() {
+        let imports = [
+            "use dojo::contract::components::upgradeable::upgradeable_cpt;",
+            "use dojo::contract::components::world_provider::{world_provider_cpt, world_provider_cpt::InternalTrait as WorldProviderInternal, IWorldProvider};",
+        ];
+
+        let (code, _) = expansion("", "mod actions {}");
+        assert!(imports.iter().all(|import| code.contains(import)));
+
+        let (code, _) = expansion("auto_import: false", "mod actions {}");
+        assert!(imports.iter().all(|import| !code.contains(import)));
+        assert!(code.contains("component!(path: upgradeable_cpt,"));
+    }
+
+    #[test]
+    fn empty_items_expect_a_module() {
+        for input in ["", "  \n"] {
+            let (code, diagnostics) = expansion("", input);
+
+            assert_eq!(
+                diagnostics,
+                ["Contract macro expects a module, but was applied to an empty item"],
+                "{:?}",
+                input
+            );
+            assert_eq!(code, input);
+        }
+    }
+
+    #[test]
+    fn trailing_bare_returns_are_linted() {
+        let input = "mod actions {
+            #[constructor]
+            fn constructor(ref self: ContractState) {
+                return;
+            }
+
+            fn dojo_init(self: @ContractState) {
+                let x = 1;
+                return;
+            }
+        }";
+
+        let (_, diagnostics) = expansion("warn_trailing_return", input);
+        assert_eq!(
+            diagnostics,
+            [
+                "The 'constructor' of contract 'actions' ends with a redundant 'return;'.",
+                "The 'dojo_init' of contract 'actions' ends with a redundant 'return;'."
+            ]
+        );
+        assert!(expansion("", input).1.is_empty());
+    }
+
+    #[test]
+    fn emit_helpers_wrap_the_component_events() {
+        let (code, diagnostics) = expansion("emit_helpers", "mod actions {}");
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(code.contains(
+            "fn emit_upgradeable_event(ref self: ContractState, event: upgradeable_cpt::Event) {
+            self.emit(Event::UpgradeableEvent(event));
+        }"
+        ));
+        assert!(code.contains("fn emit_world_provider_event(ref self: ContractState, event: world_provider_cpt::Event)"));
+
+        let (code, _) = expansion("", "mod actions {}");
+        assert!(!code.contains("fn emit_upgradeable_event("));
+    }
+
+    #[test]
+    fn patches_are_overridden_from_the_environment() {
+        // A variable of its own, so parallel expansions keep the built-in patches.
+        let env_var = "DOJO_TEST_CONTRACT_PATCH";
+        let path = std::env::temp_dir().join(format!("dojo-patch-{}.cairo", std::process::id()));
+        let required = ["$name$", "$body$"];
+
+        assert_eq!(
+            load_patch(env_var, CONTRACT_PATCH, &required).unwrap(),
+            CONTRACT_PATCH
+        );
+
+        std::fs::write(&path, "mod $name$ { $body$ }").unwrap();
+        std::env::set_var(env_var, &path);
+        assert_eq!(
+            load_patch(env_var, CONTRACT_PATCH, &required).unwrap(),
+            "mod $name$ { $body$ }"
+        );
+
+        std::fs::write(&path, "mod $name$ {}").unwrap();
+        assert_eq!(
+            load_patch(env_var, CONTRACT_PATCH, &required)
+                .unwrap_err()
+                .message,
+            format!(
+                "The patch file '{}' set by `{}` is missing the placeholder '$body$'.",
+                path.display(),
+                env_var
+            )
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_patch(env_var, CONTRACT_PATCH, &required)
+            .unwrap_err()
+            .message
+            .starts_with(&format!(
+                "Failed to read the patch file '{}'",
+                path.display()
+            )));
+        std::env::remove_var(env_var);
+    }
+
+    #[test]
+    fn long_contract_names_are_reported() {
+        let input = "mod a_contract_name_which_is_forty_chars_abc {}";
+        let message = "The contract name 'a_contract_name_which_is_forty_chars_abc' is longer than 31 characters, and doesn't fit in a short string.";
+
+        let (code, diagnostics) = expansion("", input);
+        assert_eq!(diagnostics, [message]);
+        assert!(code.contains("mod a_contract_name_which_is_forty_chars_abc {"));
+
+        let (code, diagnostics) = expansion("strict_name_length", input);
+        assert_eq!(diagnostics, [message]);
+        assert_eq!(code, input);
+    }
+
+    #[test]
+    fn mis_targeted_items_are_named() {
+        let (_, diagnostics) = expansion("", "struct Position { x: u32 }");
+        assert_eq!(
+            diagnostics,
+            ["Contract macro can only be applied to modules, but was applied to the struct 'Position'"]
+        );
+
+        let (_, diagnostics) = expansion("", "fn move() {}");
+        assert_eq!(
+            diagnostics,
+            ["Contract macro can only be applied to modules, but was applied to the function 'move'"]
+        );
+    }
+
+    #[test]
+    fn world_is_injected_once_per_external_function() {
+        let (code, diagnostics) = expansion(
+            "inject_world",
+            "mod actions {
+    #[abi(embed_v0)]
+    impl ActionsImpl of IActions<ContractState> {
+        fn spawn(ref self: ContractState) {
+            let x = 1;
+        }
+        fn reset(ref self: ContractState) {
+            let world = self.world_provider.world_dispatcher();
+        }
+    }
+
+    #[external(v0)]
+    fn moves(self: @ContractState) -> u32 {
+        0
+    }
+
+    fn helper(self: @ContractState) {}
+}",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(
+            code.matches("let world = self.world_provider.world_dispatcher();")
+                .count(),
+            3
+        );
+        let helper = &code[code.find("fn helper(").unwrap()..];
+        assert!(!helper[..helper.find('}').unwrap()].contains("let world"));
+    }
+
+    #[test]
+    fn empty_storage_is_merged_with_the_injected_members() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {\n    #[storage]\n    struct Storage {}\n}",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert_eq!(code.matches("struct Storage {").count(), 1);
+        assert!(code.contains("#[substorage(v0)]\n        world_provider: "));
+        assert!(!code.contains(",,"));
+    }
+
+    #[test]
+    fn trailing_comments_of_merged_items_are_kept() {
+        // The last member and variant, with or without their trailing comma.
+        for comma in [",", ""] {
+            let (code, diagnostics) = expansion(
+                "",
+                &format!(
+                    "mod actions {{
+    #[storage]
+    struct Storage {{
+        moves: u32{comma} // per player
+    }}
+
+    #[event]
+    #[derive(Drop, starknet::Event)]
+    enum Event {{
+        Moved: Moved{comma} // emitted on every move
+    }}
+
+    #[derive(Drop, starknet::Event)]
+    struct Moved {{}}
+}}"
+                ),
+            );
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert!(parses(&code));
+            assert!(
+                code.contains("// --- end ---\n        moves: u32, // per player\n\n    }"),
+                "{}",
+                code
+            );
+            assert!(
+                code.contains(
+                    "// --- end ---\n        Moved: Moved, // emitted on every move\n\n    }"
+                ),
+                "{}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn constructor_runs_the_unchecked_init() {
+        let (code, diagnostics) = expansion(
+            "init_from_constructor",
+            "mod actions {
+    #[constructor]
+    fn constructor(ref self: ContractState, count: u32) {}
+
+    fn dojo_init(ref self: ContractState, count: u32) {
+        self.count.write(count);
+    }
+}",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+
+        // The constructor, `dojo_init` and the unchecked init are emitted in this order.
+        let constructor = code.find("fn constructor(").unwrap();
+        let init = code.find("fn dojo_init(").unwrap();
+        let unchecked = code.find("fn dojo_init_unchecked(").unwrap();
+        let (constructor, init, unchecked) = (
+            &code[constructor..init],
+            &code[init..unchecked],
+            &code[unchecked..],
+        );
+
+        assert!(constructor.contains("self.dojo_init_unchecked(count);"));
+        assert!(init.contains("Only the world can init contract"));
+        assert!(init.contains("self.dojo_init_unchecked(count);"));
+        assert!(!init.contains("self.count.write(count);"));
+        assert!(
+            unchecked.starts_with("fn dojo_init_unchecked(ref self: ContractState, count: u32)")
+        );
+        assert!(unchecked.contains("self.count.write(count);"));
+        assert!(!unchecked.contains("Only the world can init contract"));
+
+        let (code, diagnostics) = expansion("init_from_constructor", "mod actions {}");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("fn dojo_init_unchecked(self: @ContractState)"));
+        assert_eq!(code.matches("self.dojo_init_unchecked();").count(), 2);
+    }
+
+    #[test]
+    fn malformed_parameters_are_rejected() {
+        let input = "mod actions {
+    #[constructor]
+    fn constructor(ref self: ContractState, count: ) {}
+}";
+        let (code, diagnostics) = expansion("", input);
+        assert_eq!(
+            diagnostics,
+            ["The parameters of 'constructor' in contract 'actions' are malformed: 'ref self: ContractState, count:'."]
+        );
+        assert_eq!(code, input);
+    }
+
+    #[test]
+    fn an_interface_is_generated_per_impl() {
+        let (code, diagnostics) = expansion(
+            "gen_interface(IMoves, ISpawn)",
+            "mod actions {
+    #[abi(per_item)]
+    #[generate_trait]
+    impl MovesImpl of MovesTrait {
+        #[external(v0)]
+        fn left(ref self: ContractState) {}
+        #[external(v0)]
+        fn right(ref self: ContractState) {}
+    }
+
+    #[abi(per_item)]
+    #[generate_trait]
+    impl SpawnImpl of SpawnTrait {
+        #[external(v0)]
+        fn spawn(ref self: ContractState, x: u32) {}
+    }
+}",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let moves = &code[code.find("pub trait IMoves<TContractState> {").unwrap()..];
+        let moves = &moves[..moves.find('}').unwrap()];
+        assert!(moves.contains("fn left(ref self: TContractState);"));
+        assert!(moves.contains("fn right(ref self: TContractState);"));
+        assert!(!moves.contains("fn spawn("));
+
+        let spawn = &code[code.find("pub trait ISpawn<TContractState> {").unwrap()..];
+        let spawn = &spawn[..spawn.find('}').unwrap()];
+        assert!(spawn.contains("fn spawn(ref self: TContractState, x: u32);"));
+        assert!(!spawn.contains("fn left("));
+    }
+
+    #[test]
+    fn store_is_derived_on_local_storage_value_types() {
+        for item in [
+            "/// Position of a player.\n    struct Position { x: u32 }",
+            "/// Position of a player.\n    #[derive(Drop, Serde)]\n    struct Position { x: u32 }",
+        ] {
+            let (code, diagnostics) = expansion(
+                "derive_store",
+                &format!(
+                    "mod actions {{
+    {}
+
+    #[storage]
+    struct Storage {{
+        position: Position,
+    }}
+}}",
+                    item
+                ),
+            );
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert!(parses(&code));
+            assert_eq!(
+                code.matches("/// Position of a player.").count(),
+                1,
+                "{}",
+                item
+            );
+
+            let position = &code[..code.find("struct Position {").unwrap()];
+            let derive = position.rsplit("#[derive(").next().unwrap();
+            assert!(derive.contains("starknet::Store"), "{}", item);
+        }
+    }
+
+    #[test]
+    fn origin_init_check_replaces_the_caller_check() {
+        let input = "mod actions { fn dojo_init(self: @ContractState) {} }";
+        let init = |attr: &str| {
+            let (code, diagnostics) = expansion(attr, input);
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert!(parses(&code));
+            code[code.find("fn dojo_init(").unwrap()..].to_string()
+        };
+
+        let origin = init("init_check: \"origin\"");
+        assert!(origin.contains(
+            "let origin = starknet::get_tx_info().unbox().account_contract_address;
+            if origin != self.world_provider.world_dispatcher().contract_address {"
+        ));
+        assert!(!origin.contains("get_caller_address"), "{}", origin);
+        assert!(!origin.contains("is_owner"), "{}", origin);
+
+        let caller = init("");
+        assert!(caller.contains(
+            "if starknet::get_caller_address() != self.world_provider.world_dispatcher().contract_address {"
+        ));
+        assert!(!caller.contains("get_tx_info"), "{}", caller);
+    }
+
+    #[test]
+    fn unused_imports_are_reported() {
+        let (_, diagnostics) = expansion(
+            "warn_unused_imports",
+            "mod actions {
+    use starknet::{ContractAddress, ClassHash};
+    use dojo::world::IWorldDispatcherTrait;
+
+    #[storage]
+    struct Storage {
+        owner: ContractAddress,
+    }
+}",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The contract 'actions' has unused imports: 'ClassHash'."]
+        );
+    }
+
+    #[test]
+    fn private_impl_methods_are_not_entrypoints() {
+        let (code, diagnostics) = expansion(
+            "",
+            "mod actions {
+    #[generate_trait]
+    impl HelpersImpl of HelpersTrait {
+        fn constructor(ref self: ContractState, count: u32) {
+            self.count.write(count);
+        }
+    }
+}",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+        assert!(code.contains(
+            "impl HelpersImpl of HelpersTrait {
+        fn constructor(ref self: ContractState, count: u32) {
+            self.count.write(count);
+        }
+    }"
+        ));
+
+        // The generated constructor takes no parameters, as the helper isn't the constructor.
+        assert_eq!(code.matches("#[constructor]").count(), 1);
+        let constructor = &code[code.find("#[constructor]").unwrap()..];
+        assert!(constructor.contains("fn constructor(ref self: ContractState) {"));
+    }
+
+    #[test]
+    fn default_ctor_body_follows_the_initializer() {
+        let (code, diagnostics) = expansion(
+            "default_ctor_body: \"self.count.write(1);\"",
+            "mod actions {}",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(parses(&code));
+
+        let constructor = &code[code.find("fn constructor(").unwrap()..];
+        let initializer = constructor
+            .find("self.world_provider.initializer();")
+            .unwrap();
+        let statement = constructor.find("self.count.write(1);").unwrap();
+        assert!(initializer < statement);
+        assert!(statement < constructor.find('}').unwrap());
+
+        assert_eq!(
+            expansion("default_ctor_body: \"let x = ;\"", "mod actions {}").1,
+            ["Invalid statements 'let x = ;' in 'default_ctor_body'."]
+        );
+    }
+}
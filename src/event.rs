@@ -0,0 +1,197 @@
+//! `dojo_event` macro, making a struct a Dojo event emitted through the world.
+//!
+//! It isn't named `event`, as it would clash with the `#[event]` attribute of Starknet
+//! contracts.
+
+use cairo_lang_macro::{Diagnostic, ProcMacroResult, TokenStream};
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::node::ast::{self, ModuleItem};
+use cairo_lang_syntax::node::helpers::QueryAttrs;
+use cairo_lang_syntax::node::{Terminal, TypedSyntaxNode};
+
+use crate::args::EventArgs;
+use crate::utils::{error_result, is_name_valid, merge_attributes, stable_hash};
+
+const EVENT_PATCH: &str = include_str!("patches/event.patch.cairo");
+const KEY_ATTR: &str = "key";
+
+/// Derives every event struct must have.
+const REQUIRED_DERIVES: [&str; 3] = ["Drop", "Serde", "Introspect"];
+
+/// Expands a `#[dojo_event]` struct given as Cairo source.
+pub fn expand_event(input: &str) -> ProcMacroResult {
+    expand(&EventArgs::default(), input)
+}
+
+pub(crate) fn expand(args: &EventArgs, input: &str) -> ProcMacroResult {
+    let db = SimpleParserDatabase::default();
+    let (root, _diagnostics) = db.parse_virtual_with_diagnostics(input);
+
+    let struct_ast = ast::SyntaxFile::from_syntax_node(&db, root)
+        .items(&db)
+        .elements(&db)
+        .into_iter()
+        .find_map(|item| match item {
+            ModuleItem::Struct(struct_ast) => Some(struct_ast),
+            _ => None,
+        });
+
+    let Some(struct_ast) = struct_ast else {
+        return error_result(
+            input,
+            Diagnostic::error("Event macro can only be applied to structs"),
+        );
+    };
+
+    let event_type = struct_ast.name(&db).text(&db).to_string();
+
+    if !is_name_valid(&event_type) {
+        return error_result(
+            input,
+            Diagnostic::error(format!(
+                "The event name '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+                event_type
+            )),
+        );
+    }
+
+    if let ast::OptionWrappedGenericParamList::WrappedGenericParamList(generics) =
+        struct_ast.generic_params(&db)
+    {
+        return error_result(
+            input,
+            Diagnostic::error(format!(
+                "The event '{}' cannot be generic, but declares '{}'.",
+                event_type,
+                generics.as_syntax_node().get_text_without_trivia(&db)
+            )),
+        );
+    }
+
+    // Source order is the serialization order of keys and values.
+    let (keys, values): (Vec<_>, Vec<_>) = struct_ast
+        .members(&db)
+        .elements(&db)
+        .into_iter()
+        .map(|m| (m.name(&db).text(&db).to_string(), m.has_attr(&db, KEY_ATTR)))
+        .partition(|(_, key)| *key);
+
+    if keys.is_empty() {
+        return error_result(
+            input,
+            Diagnostic::error(format!(
+                "The event '{}' must define at least one #[key] member.",
+                event_type
+            )),
+        );
+    }
+
+    let serialize = |members: &[(String, bool)]| {
+        members
+            .iter()
+            .map(|(name, _)| {
+                format!(
+                    "core::serde::Serde::serialize(self.{}, ref serialized);",
+                    name
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let event_derives = merge_attributes(&db, struct_ast.attributes(&db), &[], &REQUIRED_DERIVES);
+
+    // The struct is re-emitted as written, with its attributes replaced by the merged ones.
+    let struct_text = struct_ast.as_syntax_node().get_text(&db);
+    let attributes_text = struct_ast.attributes(&db).as_syntax_node().get_text(&db);
+    let event_struct = format!(
+        "{}\n{}",
+        event_derives,
+        struct_text
+            .strip_prefix(attributes_text.as_str())
+            .unwrap_or(&struct_text)
+    );
+
+    let unique_hash = stable_hash(&format!(
+        "{},{}",
+        event_type,
+        struct_ast
+            .members(&db)
+            .elements(&db)
+            .iter()
+            .map(|m| m.name(&db).text(&db).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    ));
+
+    let event_code = EVENT_PATCH
+        .replace("$historical$", &args.historical.to_string())
+        .replace("$serialized_keys$", &serialize(&keys))
+        .replace("$serialized_values$", &serialize(&values))
+        .replace("$unique_hash$", &unique_hash)
+        .replace("$type_name$", &event_type);
+
+    ProcMacroResult::new(TokenStream::new(format!(
+        "{}\n{}",
+        event_struct, event_code
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::messages;
+
+    #[test]
+    fn historical_mode_is_selected_by_the_arguments() {
+        let input = "struct Moved { #[key] player: ContractAddress, x: u32 }";
+        let expansion = |attr: &str| {
+            let result = expand(&EventArgs::parse(attr).unwrap(), input);
+            assert!(messages(&result).is_empty());
+            result.token_stream.to_string()
+        };
+
+        let historical = "fn historical() -> bool {\n        true\n    }";
+        let latest = "fn historical() -> bool {\n        false\n    }";
+        assert!(expansion("").contains(historical));
+        assert!(expansion("dojo_event(historical: false)").contains(latest));
+        assert!(expansion("historical: false").contains(latest));
+
+        assert_eq!(
+            EventArgs::parse("mode: \"latest\"")
+                .unwrap_err()
+                .into_iter()
+                .map(|d| d.message)
+                .collect::<Vec<_>>(),
+            ["Unknown dojo_event argument 'mode'."]
+        );
+    }
+
+    #[test]
+    fn event_contracts_are_made_unique_by_their_members() {
+        let unique_hash = |input: &str| {
+            let result = expand_event(input);
+            assert!(messages(&result).is_empty());
+            let code = result.token_stream.to_string();
+            assert!(
+                code.contains("selector_from_namespace_and_name(namespace_hash, @Self::name())")
+            );
+            let hash = &code[code.find("let _hash = ").unwrap()..];
+            hash[..hash.find(';').unwrap()].to_string()
+        };
+
+        let moved = unique_hash("struct Moved { #[key] player: ContractAddress, x: u32 }");
+        assert_eq!(
+            moved,
+            unique_hash("struct Moved { #[key] player: ContractAddress, x: u32 }")
+        );
+        assert_ne!(
+            moved,
+            unique_hash("struct Moved { #[key] player: ContractAddress, y: u32 }")
+        );
+        assert_ne!(
+            moved,
+            unique_hash("struct Jumped { #[key] player: ContractAddress, x: u32 }")
+        );
+    }
+}
@@ -0,0 +1,657 @@
+//! `Introspect` derive, describing the storage layout and the type of a struct or an enum.
+
+use cairo_lang_macro::{Diagnostic, ProcMacroResult, TokenStream};
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::node::ast::{self, GenericArg, GenericArgValue, ModuleItem, PathSegment};
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::helpers::{PathSegmentEx, QueryAttrs};
+use cairo_lang_syntax::node::kind::SyntaxKind;
+use cairo_lang_syntax::node::{Terminal, TypedSyntaxNode};
+
+const KEY_ATTR: &str = "key";
+
+/// Primitive types with a fixed size known at expansion time.
+const PRIMITIVE_SIZES: &[(&str, usize)] = &[
+    ("felt252", 1),
+    ("bool", 1),
+    ("u8", 1),
+    ("u16", 1),
+    ("u32", 1),
+    ("u64", 1),
+    ("u128", 1),
+    ("usize", 1),
+    ("u256", 2),
+    ("i8", 1),
+    ("i16", 1),
+    ("i32", 1),
+    ("i64", 1),
+    ("i128", 1),
+    ("bytes31", 1),
+    ("ContractAddress", 1),
+    ("ClassHash", 1),
+    ("EthAddress", 1),
+];
+
+/// Type of a member, as far as its layout is concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MemberTy {
+    ByteArray,
+    /// `Array<T>` or `Span<T>`.
+    Array(Box<MemberTy>),
+    Tuple(Vec<MemberTy>),
+    /// Core enums such as `Option<T>` and `Result<T, E>`, laid out inline.
+    Enum {
+        name: String,
+        variants: Vec<(String, MemberTy)>,
+    },
+    /// Any other type, relying on its own `Introspect` implementation.
+    Other {
+        name: String,
+    },
+}
+
+/// Size of a member, split between what is known at expansion time and what is not.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Size {
+    fixed: usize,
+    /// Types whose size is only known through their `Introspect` implementation.
+    unknown: Vec<String>,
+    dynamic: bool,
+}
+
+impl Size {
+    fn fixed(size: usize) -> Self {
+        Self {
+            fixed: size,
+            ..Default::default()
+        }
+    }
+
+    fn dynamic() -> Self {
+        Self {
+            dynamic: true,
+            ..Default::default()
+        }
+    }
+
+    fn add(mut self, other: Size) -> Self {
+        self.fixed += other.fixed;
+        self.unknown.extend(other.unknown);
+        self.dynamic |= other.dynamic;
+        self
+    }
+
+    /// Expression of the `Introspect::size` function body.
+    fn to_cairo(&self) -> String {
+        if self.dynamic {
+            return "Option::None".to_string();
+        }
+
+        if self.unknown.is_empty() {
+            return format!("Option::Some({})", self.fixed);
+        }
+
+        format!(
+            "let sizes: Array<Option<usize>> = array![
+                {}
+            ];
+
+            if dojo::utils::any_none(@sizes) {{
+                return Option::None;
+            }}
+            Option::Some(dojo::utils::sum(sizes) + {})",
+            self.unknown
+                .iter()
+                .map(|ty| format!("dojo::meta::introspect::Introspect::<{}>::size(),", ty))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            self.fixed
+        )
+    }
+}
+
+impl MemberTy {
+    fn from_ast(db: &dyn SyntaxGroup, ty: &ast::Expr) -> Result<Self, Diagnostic> {
+        let text = ty.as_syntax_node().get_text_without_trivia(db);
+
+        match ty {
+            ast::Expr::Tuple(tuple) => Ok(MemberTy::Tuple(
+                tuple
+                    .expressions(db)
+                    .elements(db)
+                    .iter()
+                    .map(|e| Self::from_ast(db, e))
+                    .collect::<Result<_, _>>()?,
+            )),
+            ast::Expr::Path(path) => {
+                let Some(PathSegment::WithGenericArgs(segment)) = path.elements(db).pop() else {
+                    return Ok(if text == "ByteArray" {
+                        MemberTy::ByteArray
+                    } else {
+                        MemberTy::Other { name: text }
+                    });
+                };
+
+                let args = segment
+                    .generic_args(db)
+                    .generic_args(db)
+                    .elements(db)
+                    .into_iter()
+                    .map(|arg| match arg {
+                        GenericArg::Unnamed(arg) => match arg.value(db) {
+                            GenericArgValue::Expr(e) => Self::from_ast(db, &e.expr(db)),
+                            GenericArgValue::Underscore(_) => Err(unsupported(&text)),
+                        },
+                        GenericArg::Named(_) => Err(unsupported(&text)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let variants = |names: &[&str]| {
+                    names
+                        .iter()
+                        .map(|n| n.to_string())
+                        .zip(args.iter().cloned())
+                        .collect::<Vec<_>>()
+                };
+
+                match (segment.ident(db).text(db).as_str(), args.as_slice()) {
+                    ("Array" | "Span", [item]) => {
+                        // Arrays of pairs are maps, which are keyed by primitive types only.
+                        if let MemberTy::Tuple(pair) = item {
+                            if let [key, _] = pair.as_slice() {
+                                if !matches!(key, MemberTy::Other { name } if primitive_size(name).is_some())
+                                {
+                                    return Err(Diagnostic::error(format!(
+                                        "Unsupported key type '{}' in '{}' for Introspect, arrays of pairs must be keyed by a primitive type.",
+                                        key.name(),
+                                        text
+                                    )));
+                                }
+                            }
+                        }
+                        Ok(MemberTy::Array(Box::new(item.clone())))
+                    }
+                    ("Option", [_]) => Ok(MemberTy::Enum {
+                        name: text,
+                        variants: [variants(&["Some"]), vec![("None".into(), unit())]].concat(),
+                    }),
+                    ("Result", [_, _]) => Ok(MemberTy::Enum {
+                        name: text,
+                        variants: variants(&["Ok", "Err"]),
+                    }),
+                    _ => Err(unsupported(&text)),
+                }
+            }
+            _ => Ok(MemberTy::Other { name: text }),
+        }
+    }
+
+    /// Type name, as written in the source.
+    fn name(&self) -> String {
+        match self {
+            MemberTy::ByteArray => "ByteArray".to_string(),
+            MemberTy::Array(item) => format!("Array<{}>", item.name()),
+            MemberTy::Tuple(items) => format!(
+                "({})",
+                items
+                    .iter()
+                    .map(|i| i.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            MemberTy::Enum { name, .. } | MemberTy::Other { name } => name.clone(),
+        }
+    }
+
+    fn layout(&self) -> String {
+        match self {
+            MemberTy::ByteArray => "dojo::meta::Layout::ByteArray".to_string(),
+            MemberTy::Array(item) => {
+                format!(
+                    "dojo::meta::Layout::Array(array![{}].span())",
+                    item.layout()
+                )
+            }
+            MemberTy::Tuple(items) => format!(
+                "dojo::meta::Layout::Tuple(array![{}].span())",
+                items
+                    .iter()
+                    .map(|i| i.layout())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            MemberTy::Enum { variants, .. } => format!(
+                "dojo::meta::Layout::Enum(array![{}].span())",
+                variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, ty))| format!(
+                        "dojo::meta::FieldLayout {{ selector: {}, layout: {} }}",
+                        i,
+                        ty.layout()
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(",\n")
+            ),
+            MemberTy::Other { name } => {
+                format!("dojo::meta::introspect::Introspect::<{}>::layout()", name)
+            }
+        }
+    }
+
+    fn ty(&self) -> String {
+        match self {
+            MemberTy::ByteArray => "dojo::meta::introspect::Ty::ByteArray".to_string(),
+            MemberTy::Array(item) => {
+                format!(
+                    "dojo::meta::introspect::Ty::Array(array![{}].span())",
+                    item.ty()
+                )
+            }
+            MemberTy::Tuple(items) => format!(
+                "dojo::meta::introspect::Ty::Tuple(array![{}].span())",
+                items.iter().map(|i| i.ty()).collect::<Vec<_>>().join(", ")
+            ),
+            MemberTy::Enum { name, variants } => format!(
+                "dojo::meta::introspect::Ty::Enum(
+                    dojo::meta::introspect::Enum {{
+                        name: '{}',
+                        attrs: array![].span(),
+                        children: array![{}].span()
+                    }}
+                )",
+                name,
+                variants
+                    .iter()
+                    .map(|(variant, ty)| format!("('{}', {})", variant, ty.ty()))
+                    .collect::<Vec<_>>()
+                    .join(",\n")
+            ),
+            MemberTy::Other { name } => {
+                format!("dojo::meta::introspect::Introspect::<{}>::ty()", name)
+            }
+        }
+    }
+
+    fn size(&self) -> Size {
+        match self {
+            MemberTy::ByteArray | MemberTy::Array(_) => Size::dynamic(),
+            MemberTy::Tuple(items) => items
+                .iter()
+                .fold(Size::default(), |size, item| size.add(item.size())),
+            // Enums have a fixed size only when all their variants have the same known size.
+            MemberTy::Enum { variants, .. } => {
+                let sizes = variants.iter().map(|(_, ty)| ty.size()).collect::<Vec<_>>();
+                match sizes.first() {
+                    Some(first)
+                        if first.unknown.is_empty()
+                            && !first.dynamic
+                            && sizes.iter().all(|s| s == first) =>
+                    {
+                        Size::fixed(first.fixed + 1)
+                    }
+                    _ => Size::dynamic(),
+                }
+            }
+            MemberTy::Other { name } => match primitive_size(name) {
+                Some(size) => Size::fixed(size),
+                None => Size {
+                    unknown: vec![name.clone()],
+                    ..Default::default()
+                },
+            },
+        }
+    }
+}
+
+pub(crate) fn primitive_size(name: &str) -> Option<usize> {
+    let short_name = name.rsplit("::").next().unwrap_or(name);
+    PRIMITIVE_SIZES
+        .iter()
+        .find(|(p, _)| *p == short_name)
+        .map(|(_, size)| *size)
+}
+
+/// Size of a type known at expansion time, `None` if it's dynamic or depends on the
+/// `Introspect` impl of another type.
+pub(crate) fn fixed_size(db: &dyn SyntaxGroup, ty: &ast::Expr) -> Option<usize> {
+    let size = MemberTy::from_ast(db, ty).ok()?.size();
+    (!size.dynamic && size.unknown.is_empty()).then_some(size.fixed)
+}
+
+pub(crate) fn is_dynamic(db: &dyn SyntaxGroup, ty: &ast::Expr) -> bool {
+    MemberTy::from_ast(db, ty).is_ok_and(|ty| ty.size().dynamic)
+}
+
+/// Whether the type refers to the type `name`, directly or through the tuples, fixed arrays
+/// and generic args containing it, which would make its layout infinitely recursive.
+pub(crate) fn refers_to(db: &dyn SyntaxGroup, ty: &ast::Expr, name: &str) -> bool {
+    ty.as_syntax_node().descendants(db).any(|node| {
+        node.kind(db) == SyntaxKind::ExprPath
+            && matches!(
+                ast::ExprPath::from_syntax_node(db, node).elements(db).as_slice(),
+                [segment] if segment.identifier_ast(db).text(db) == name
+            )
+    })
+}
+
+fn unit() -> MemberTy {
+    MemberTy::Tuple(vec![])
+}
+
+fn unsupported(ty: &str) -> Diagnostic {
+    Diagnostic::error(format!(
+        "Unsupported generic type '{}' for Introspect, only Array, Span, Option and Result are supported.",
+        ty
+    ))
+}
+
+/// Expands the `Introspect` derive of a struct or an enum given as Cairo source.
+///
+/// Only the generated impl is returned, the item itself is kept by the compiler.
+pub fn expand_introspect(input: &str) -> ProcMacroResult {
+    let db = SimpleParserDatabase::default();
+    let (root, _diagnostics) = db.parse_virtual_with_diagnostics(input);
+
+    let result = ast::SyntaxFile::from_syntax_node(&db, root)
+        .items(&db)
+        .elements(&db)
+        .into_iter()
+        .find_map(|item| match item {
+            ModuleItem::Struct(struct_ast) => Some(struct_introspect(&db, &struct_ast)),
+            ModuleItem::Enum(enum_ast) => Some(enum_introspect(&db, &enum_ast)),
+            _ => None,
+        })
+        .unwrap_or_else(|| {
+            Err(vec![Diagnostic::error(
+                "Introspect can only be derived on structs and enums",
+            )])
+        });
+
+    match result {
+        Ok(code) => ProcMacroResult::new(TokenStream::new(code)),
+        Err(diagnostics) => {
+            ProcMacroResult::new(TokenStream::empty()).with_diagnostics(diagnostics.into())
+        }
+    }
+}
+
+fn struct_introspect(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ast::ItemStruct,
+) -> Result<String, Vec<Diagnostic>> {
+    let name = struct_ast.name(db).text(db).to_string();
+
+    if let ast::OptionWrappedGenericParamList::WrappedGenericParamList(_) =
+        struct_ast.generic_params(db)
+    {
+        return Err(vec![Diagnostic::error(format!(
+            "Introspect cannot be derived on the generic struct '{}'.",
+            name
+        ))]);
+    }
+
+    let mut diagnostics = vec![];
+    let mut layouts = vec![];
+    let mut children = vec![];
+    let mut size = Size::default();
+
+    for member in struct_ast.members(db).elements(db) {
+        let member_name = member.name(db).text(db).to_string();
+        let key = member.has_attr(db, KEY_ATTR);
+        let member_ty = member.type_clause(db).ty(db);
+
+        if refers_to(db, &member_ty, &name) {
+            diagnostics.push(Diagnostic::error(format!(
+                "The struct '{}' cannot contain itself, but its member '{}' is of type '{}'.",
+                name,
+                member_name,
+                member_ty.as_syntax_node().get_text_without_trivia(db)
+            )));
+            continue;
+        }
+
+        let ty = match MemberTy::from_ast(db, &member_ty) {
+            Ok(ty) => ty,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        children.push(format!(
+            "dojo::meta::introspect::Member {{
+                name: '{}',
+                attrs: array![{}].span(),
+                ty: {}
+            }}",
+            member_name,
+            if key { "'key'" } else { "" },
+            ty.ty()
+        ));
+
+        // Keys are not stored, and so are not part of the layout.
+        if !key {
+            layouts.push(format!(
+                "dojo::meta::FieldLayout {{
+                    selector: selector!(\"{}\"),
+                    layout: {}
+                }}",
+                member_name,
+                ty.layout()
+            ));
+            size = size.add(ty.size());
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    Ok(format!(
+        "impl {name}Introspect of dojo::meta::introspect::Introspect<{name}> {{
+    #[inline(always)]
+    fn size() -> Option<usize> {{
+        {size}
+    }}
+
+    fn layout() -> dojo::meta::Layout {{
+        dojo::meta::Layout::Struct(
+            array![
+                {layouts}
+            ].span()
+        )
+    }}
+
+    #[inline(always)]
+    fn ty() -> dojo::meta::introspect::Ty {{
+        dojo::meta::introspect::Ty::Struct(
+            dojo::meta::introspect::Struct {{
+                name: '{name}',
+                attrs: array![].span(),
+                children: array![
+                    {children}
+                ].span()
+            }}
+        )
+    }}
+}}
+",
+        name = name,
+        size = size.to_cairo(),
+        layouts = layouts.join(",\n"),
+        children = children.join(",\n"),
+    ))
+}
+
+/// Enums are laid out as their variant index followed by the variant data, unit variants
+/// having no data.
+fn enum_introspect(
+    db: &dyn SyntaxGroup,
+    enum_ast: &ast::ItemEnum,
+) -> Result<String, Vec<Diagnostic>> {
+    let name = enum_ast.name(db).text(db).to_string();
+
+    if let ast::OptionWrappedGenericParamList::WrappedGenericParamList(_) =
+        enum_ast.generic_params(db)
+    {
+        return Err(vec![Diagnostic::error(format!(
+            "Introspect cannot be derived on the generic enum '{}'.",
+            name
+        ))]);
+    }
+
+    let mut diagnostics = vec![];
+    let mut variants = vec![];
+
+    for variant in enum_ast.variants(db).elements(db) {
+        let ty = match variant.type_clause(db) {
+            ast::OptionTypeClause::Empty(_) => Ok(unit()),
+            ast::OptionTypeClause::TypeClause(clause) if refers_to(db, &clause.ty(db), &name) => {
+                Err(Diagnostic::error(format!(
+                    "The enum '{}' cannot contain itself, but its variant '{}' is of type '{}'.",
+                    name,
+                    variant.name(db).text(db),
+                    clause.ty(db).as_syntax_node().get_text_without_trivia(db)
+                )))
+            }
+            ast::OptionTypeClause::TypeClause(clause) => MemberTy::from_ast(db, &clause.ty(db)),
+        };
+
+        match ty {
+            Ok(ty) => variants.push((variant.name(db).text(db).to_string(), ty)),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let ty = MemberTy::Enum {
+        name: name.clone(),
+        variants,
+    };
+
+    Ok(format!(
+        "impl {name}Introspect of dojo::meta::introspect::Introspect<{name}> {{
+    #[inline(always)]
+    fn size() -> Option<usize> {{
+        {size}
+    }}
+
+    fn layout() -> dojo::meta::Layout {{
+        {layout}
+    }}
+
+    #[inline(always)]
+    fn ty() -> dojo::meta::introspect::Ty {{
+        {ty}
+    }}
+}}
+",
+        name = name,
+        size = ty.size().to_cairo(),
+        layout = ty.layout(),
+        ty = ty.ty(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::messages;
+
+    /// Introspected type of the only member of a struct, as written in `ty`.
+    fn member_ty(ty: &str) -> Result<MemberTy, Diagnostic> {
+        let db = SimpleParserDatabase::default();
+        let (root, _) = db.parse_virtual_with_diagnostics(format!("struct S {{ x: {} }}", ty));
+        let file = ast::SyntaxFile::from_syntax_node(&db, root);
+        let Some(ModuleItem::Struct(struct_ast)) = file.items(&db).elements(&db).pop() else {
+            panic!("not a struct");
+        };
+        let member = struct_ast.members(&db).elements(&db).remove(0);
+        MemberTy::from_ast(&db, &member.type_clause(&db).ty(&db))
+    }
+
+    #[test]
+    fn option_is_an_enum_layout() {
+        let ty = member_ty("Option<u32>").unwrap();
+        assert_eq!(
+            ty.layout(),
+            "dojo::meta::Layout::Enum(array![dojo::meta::FieldLayout { selector: 0, layout: dojo::meta::introspect::Introspect::<u32>::layout() },
+dojo::meta::FieldLayout { selector: 1, layout: dojo::meta::Layout::Tuple(array![].span()) }].span())"
+        );
+        assert_eq!(ty.size(), Size::dynamic());
+    }
+
+    #[test]
+    fn result_is_an_enum_layout() {
+        let ty = member_ty("Result<felt252, felt252>").unwrap();
+        assert_eq!(
+            ty.layout(),
+            "dojo::meta::Layout::Enum(array![dojo::meta::FieldLayout { selector: 0, layout: dojo::meta::introspect::Introspect::<felt252>::layout() },
+dojo::meta::FieldLayout { selector: 1, layout: dojo::meta::introspect::Introspect::<felt252>::layout() }].span())"
+        );
+        assert_eq!(ty.size(), Size::fixed(2));
+    }
+
+    #[test]
+    fn other_generics_are_rejected() {
+        let result = expand_introspect("struct S { x: Felt252Dict<u8> }");
+        assert_eq!(
+            messages(&result),
+            ["Unsupported generic type 'Felt252Dict<u8>' for Introspect, only Array, Span, Option and Result are supported."]
+        );
+    }
+
+    #[test]
+    fn array_of_pairs_is_a_dynamic_map_layout() {
+        let ty = member_ty("Array<(felt252, u32)>").unwrap();
+        assert_eq!(
+            ty.layout(),
+            "dojo::meta::Layout::Array(array![dojo::meta::Layout::Tuple(array![dojo::meta::introspect::Introspect::<felt252>::layout(), dojo::meta::introspect::Introspect::<u32>::layout()].span())].span())"
+        );
+        assert!(ty.size().dynamic);
+    }
+
+    #[test]
+    fn array_of_pairs_needs_a_primitive_key() {
+        assert_eq!(
+            member_ty("Array<(ByteArray, u32)>").err().map(|d| d.message),
+            Some("Unsupported key type 'ByteArray' in 'Array<(ByteArray, u32)>' for Introspect, arrays of pairs must be keyed by a primitive type.".to_string())
+        );
+    }
+
+    #[test]
+    fn enums_have_unit_and_data_variants() {
+        let result = expand_introspect("enum State { Idle, Active: u32 }");
+        assert!(messages(&result).is_empty());
+        let code = result.token_stream.to_string();
+        assert!(code.contains("impl StateIntrospect of dojo::meta::introspect::Introspect<State>"));
+        assert!(code.contains(
+            "dojo::meta::FieldLayout { selector: 0, layout: dojo::meta::Layout::Tuple(array![].span()) }"
+        ));
+        assert!(code.contains(
+            "dojo::meta::FieldLayout { selector: 1, layout: dojo::meta::introspect::Introspect::<u32>::layout() }"
+        ));
+
+        let result = expand_introspect("enum State<T> { Idle, Active: T }");
+        assert_eq!(
+            messages(&result),
+            ["Introspect cannot be derived on the generic enum 'State'."]
+        );
+    }
+
+    #[test]
+    fn self_referential_types_are_rejected() {
+        assert_eq!(
+            messages(&expand_introspect("struct Node { next: (u32, Node) }")),
+            ["The struct 'Node' cannot contain itself, but its member 'next' is of type '(u32, Node)'."]
+        );
+        assert_eq!(
+            messages(&expand_introspect("enum Tree { Leaf, Branch: Array<Tree> }")),
+            ["The enum 'Tree' cannot contain itself, but its variant 'Branch' is of type 'Array<Tree>'."]
+        );
+    }
+}
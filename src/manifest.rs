@@ -0,0 +1,285 @@
+//! Contract manifest emission, enabled with the `emit-metadata` feature.
+//!
+//! Every `#[contract]` and `#[model]` expansion attaches its [`ContractExpansion`] or
+//! [`ModelExpansion`] as a JSON fragment of auxiliary data, keyed by kind and by name:
+//! `{"contracts": {"<name>": {...}}}`. Fragments of a whole crate are merged with
+//! [`merge_fragments`] into a single manifest, and written to the file pointed by
+//! `DOJO_MANIFEST_PATH`, if set, once compilation is done.
+
+use std::path::Path;
+use std::{env, fs, io};
+
+use cairo_lang_macro::{post_process, AuxData, PostProcessContext};
+use serde_json::{Map, Value};
+
+use crate::contract::ContractExpansion;
+use crate::model::ModelExpansion;
+use crate::utils::dojo_tag;
+
+const MANIFEST_PATH_ENV: &str = "DOJO_MANIFEST_PATH";
+const CONTRACTS_KEY: &str = "contracts";
+const MODELS_KEY: &str = "models";
+const COLLISIONS_KEY: &str = "collisions";
+
+impl ContractExpansion {
+    /// Serializes the expansion into a deterministic JSON string.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("contract manifest is always serializable")
+    }
+
+    /// Key of the contract in the manifest, qualified by its namespace if any.
+    pub fn manifest_key(&self) -> String {
+        dojo_tag(self.namespace.as_deref(), &self.name)
+    }
+}
+
+pub(crate) fn contract_aux_data(expansion: &ContractExpansion) -> AuxData {
+    fragment(CONTRACTS_KEY, &expansion.manifest_key(), expansion)
+}
+
+pub(crate) fn model_aux_data(expansion: &ModelExpansion) -> AuxData {
+    fragment(MODELS_KEY, &expansion.name, expansion)
+}
+
+fn fragment(kind: &str, key: &str, expansion: &impl serde::Serialize) -> AuxData {
+    let value = serde_json::to_value(expansion).expect("expansion metadata is always serializable");
+    let fragment = Value::Object(Map::from_iter([(
+        kind.to_string(),
+        Value::Object(Map::from_iter([(key.to_string(), value)])),
+    )]));
+    AuxData::new(fragment.to_string().into_bytes())
+}
+
+/// Merges metadata fragments into a single manifest.
+///
+/// Entries are sorted by key, so the manifest doesn't depend on the expansion order. When two
+/// fragments define the same entry differently, the last one wins and the key is returned as
+/// a collision.
+pub fn merge_fragments(fragments: impl IntoIterator<Item = Value>) -> (Value, Vec<String>) {
+    let mut manifest = Map::new();
+    let mut collisions = vec![];
+
+    for fragment in fragments {
+        let Value::Object(kinds) = fragment else {
+            continue;
+        };
+
+        for (kind, entries) in kinds {
+            let Value::Object(entries) = entries else {
+                continue;
+            };
+
+            let merged = manifest
+                .entry(kind.clone())
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .expect("manifest kinds are objects");
+
+            for (key, entry) in entries {
+                if let Some(previous) = merged.insert(key.clone(), entry.clone()) {
+                    if previous != entry {
+                        collisions.push(format!("{}.{}", kind, key));
+                    }
+                }
+            }
+        }
+    }
+
+    (Value::Object(manifest), collisions)
+}
+
+/// Merges the metadata fragments of a compilation and writes the manifest to `path`.
+///
+/// Colliding entries are listed under the `collisions` key of the manifest and returned.
+pub fn write_manifest_to(
+    path: impl AsRef<Path>,
+    aux_data: impl IntoIterator<Item = AuxData>,
+) -> io::Result<Vec<String>> {
+    let (mut manifest, collisions) = merge_fragments(aux_data.into_iter().filter_map(|aux_data| {
+        let bytes: Vec<u8> = aux_data.into();
+        serde_json::from_slice::<Value>(&bytes).ok()
+    }));
+
+    if !collisions.is_empty() {
+        manifest
+            .as_object_mut()
+            .expect("manifests are objects")
+            .insert(COLLISIONS_KEY.to_string(), Value::from(collisions.clone()));
+    }
+
+    let json = serde_json::to_string_pretty(&manifest).expect("manifests are valid JSON");
+    fs::write(path, json)?;
+
+    Ok(collisions)
+}
+
+/// Writes the manifest of the crate if `DOJO_MANIFEST_PATH` is set.
+///
+/// The hook has no diagnostics channel: collisions are recorded in the manifest, and a
+/// manifest that can't be written fails the build.
+#[post_process]
+pub fn write_manifest(context: PostProcessContext) {
+    let Ok(path) = env::var(MANIFEST_PATH_ENV) else {
+        return;
+    };
+
+    if let Err(err) = write_manifest_to(&path, context.aux_data) {
+        panic!("failed to write the dojo manifest to `{path}`: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cairo_lang_macro::ProcMacroResult;
+
+    use crate::args::ModelArgs;
+    use crate::contract::expand_contract;
+    use crate::model;
+
+    fn fragment_of(result: ProcMacroResult) -> Value {
+        let bytes: Vec<u8> = result.aux_data.expect("metadata is attached").into();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn manifest_of(input: &str) -> Value {
+        fragment_of(expand_contract(input))
+    }
+
+    #[test]
+    fn contract_manifest_lists_events_and_storage() {
+        let manifest = manifest_of(
+            "mod actions {
+                #[event]
+                #[derive(Drop, starknet::Event)]
+                enum Event {
+                    Moved: Moved,
+                }
+            }",
+        );
+
+        let contract = &manifest[CONTRACTS_KEY]["actions"];
+        assert_eq!(contract["name"], "actions");
+        assert_eq!(contract["namespace"], Value::Null);
+        assert_eq!(contract["has_init"], false);
+        assert_eq!(contract["has_constructor"], false);
+        assert_eq!(
+            contract["events"],
+            serde_json::json!(["UpgradeableEvent", "WorldProviderEvent", "Moved"])
+        );
+        assert_eq!(
+            contract["storage_fields"],
+            serde_json::json!(["upgradeable", "world_provider"])
+        );
+    }
+
+    #[test]
+    fn contract_manifest_is_deterministic() {
+        let input = "mod actions {}";
+        assert_eq!(
+            expand_contract(input).aux_data.map(Vec::<u8>::from),
+            expand_contract(input).aux_data.map(Vec::<u8>::from)
+        );
+    }
+
+    #[test]
+    fn constructor_statements_follow_the_initializer() {
+        let manifest = manifest_of(
+            "mod actions {
+                #[constructor]
+                fn constructor(ref self: ContractState) {
+                    let count = 1;
+                    self.count.write(count);
+                }
+            }",
+        );
+
+        assert_eq!(
+            manifest[CONTRACTS_KEY]["actions"]["constructor_statements"],
+            serde_json::json!([
+                "self.world_provider.initializer();",
+                "let count = 1;",
+                "self.count.write(count);"
+            ])
+        );
+    }
+
+    #[test]
+    fn legacy_models_use_the_name_selector() {
+        let input = "struct Position { #[key] player: ContractAddress, x: u32 }";
+        let selector = |attr: &str| {
+            let args = ModelArgs::parse(attr).unwrap();
+            fragment_of(model::expand(&args, input))[MODELS_KEY]["Position"].clone()
+        };
+
+        let current = selector("");
+        let legacy = selector("legacy");
+
+        assert_eq!(current["legacy"], false);
+        assert_eq!(
+            current["selector"],
+            "dojo::utils::selector_from_namespace_and_name(namespace_hash, @\"Position\")"
+        );
+        assert_eq!(legacy["legacy"], true);
+        assert_eq!(legacy["selector"], "selector!(\"Position\")");
+    }
+
+    #[test]
+    fn contract_fragments_do_not_collide() {
+        let (manifest, collisions) =
+            merge_fragments([manifest_of("mod actions {}"), manifest_of("mod spawner {}")]);
+
+        assert!(collisions.is_empty(), "{:?}", collisions);
+        assert_eq!(manifest[CONTRACTS_KEY]["actions"]["name"], "actions");
+        assert_eq!(manifest[CONTRACTS_KEY]["spawner"]["name"], "spawner");
+    }
+
+    #[test]
+    fn collisions_are_returned_and_recorded() {
+        let path = env::temp_dir().join(format!("dojo-manifest-{}.json", std::process::id()));
+        let aux_data = [
+            expand_contract("mod actions {}").aux_data.unwrap(),
+            expand_contract("mod actions { #[dojo_init] fn dojo_init(self: @ContractState) {} }")
+                .aux_data
+                .unwrap(),
+        ];
+
+        let collisions = write_manifest_to(&path, aux_data).unwrap();
+        let written: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(collisions, ["contracts.actions"]);
+        assert_eq!(
+            written[COLLISIONS_KEY],
+            serde_json::json!(["contracts.actions"])
+        );
+        assert_eq!(written[CONTRACTS_KEY]["actions"]["has_init"], true);
+    }
+
+    #[test]
+    fn write_failures_are_returned() {
+        let path = env::temp_dir()
+            .join("dojo-missing-dir")
+            .join("manifest.json");
+        let aux_data = expand_contract("mod actions {}").aux_data;
+
+        assert!(write_manifest_to(path, aux_data).is_err());
+    }
+
+    #[test]
+    fn init_params_are_listed_in_order() {
+        let manifest = manifest_of(
+            "mod actions {
+                fn dojo_init(self: @ContractState, owner: ContractAddress, counts: Array<u32>) {}
+            }",
+        );
+
+        assert_eq!(
+            manifest[CONTRACTS_KEY]["actions"]["init_params"],
+            serde_json::json!([
+                { "name": "owner", "type": "ContractAddress" },
+                { "name": "counts", "type": "Array<u32>" }
+            ])
+        );
+    }
+}
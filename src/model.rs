@@ -0,0 +1,910 @@
+use cairo_lang_macro::{Diagnostic, ProcMacroResult, TokenStream};
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::attribute::structured::AttributeStructurize;
+use cairo_lang_syntax::node::ast::{self, ModuleItem};
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::helpers::QueryAttrs;
+use cairo_lang_syntax::node::{Terminal, TypedSyntaxNode};
+
+use crate::args::ModelArgs;
+use crate::introspect::{fixed_size, is_dynamic, primitive_size, refers_to};
+use crate::utils::{error_result, is_name_valid, merge_attributes, stable_hash};
+
+const MODEL_PATCH: &str = include_str!("patches/model.patch.cairo");
+const MODEL_VALUE_PATCH: &str = include_str!("patches/model_value.patch.cairo");
+const MODEL_FIELD_STORE_PATCH: &str = include_str!("patches/model_field_store.patch.cairo");
+const KEY_ATTR: &str = "key";
+const INTROSPECT_DERIVE: &str = "Introspect";
+const INTROSPECT_PACKED_DERIVE: &str = "IntrospectPacked";
+const DEFAULT_DERIVE: &str = "Default";
+
+/// Core types without a `Default` impl, which prevent deriving it on the value struct.
+const NON_DEFAULT_TYPES: &[&str] = &["NonZero"];
+
+const CLONE_DERIVE: &str = "Clone";
+
+/// Core types which can't be cloned, checked on a best-effort basis as user types can't be.
+const NON_CLONE_TYPES: &[&str] = &["Felt252Dict", "SquashedFelt252Dict", "Felt252DictEntry"];
+
+/// Types a key member can have, as keys are hashed into the entity id.
+const KEY_TYPES: &[&str] = &[
+    "felt252",
+    "ContractAddress",
+    "bool",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "u256",
+    "usize",
+];
+
+/// Names used by the generated code taking the keys as parameters, which keys can't reuse.
+const RESERVED_KEY_NAMES: &[&str] = &["serialized"];
+
+/// Derives every model and model value struct must have.
+const REQUIRED_DERIVES: [&str; 2] = ["Drop", "Serde"];
+
+/// Summary of what the `model` macro generated for a struct.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "emit-metadata", derive(serde::Serialize))]
+pub struct ModelExpansion {
+    pub name: String,
+    pub legacy: bool,
+    /// Cairo expression of the model selector, given the `namespace_hash`.
+    pub selector: String,
+    pub layout_version: u64,
+}
+
+/// A member of a model struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Member {
+    name: String,
+    ty: String,
+    key: bool,
+}
+
+/// Expands a `#[model]` struct given as Cairo source.
+pub fn expand_model(input: &str) -> ProcMacroResult {
+    expand(&ModelArgs::default(), input)
+}
+
+pub(crate) fn expand(args: &ModelArgs, input: &str) -> ProcMacroResult {
+    let db = SimpleParserDatabase::default();
+    let (root, _diagnostics) = db.parse_virtual_with_diagnostics(input);
+
+    let struct_ast = ast::SyntaxFile::from_syntax_node(&db, root)
+        .items(&db)
+        .elements(&db)
+        .into_iter()
+        .find_map(|item| match item {
+            ModuleItem::Struct(struct_ast) => Some(struct_ast),
+            _ => None,
+        });
+
+    let Some(struct_ast) = struct_ast else {
+        return error_result(
+            input,
+            Diagnostic::error("Model macro can only be applied to structs"),
+        );
+    };
+
+    let model_type = struct_ast.name(&db).text(&db).to_string();
+
+    if !is_name_valid(&model_type) {
+        return error_result(
+            input,
+            Diagnostic::error(format!(
+                "The model name '{}' can only contain characters (a-z/A-Z), digits (0-9) and underscore (_).",
+                model_type
+            )),
+        );
+    }
+
+    // Layouts and selectors are computed from the member types as written, which a generic
+    // param would leave unknown.
+    if let ast::OptionWrappedGenericParamList::WrappedGenericParamList(generics) =
+        struct_ast.generic_params(&db)
+    {
+        return error_result(
+            input,
+            Diagnostic::error(format!(
+                "The model '{}' cannot be generic, but declares '{}'.",
+                model_type,
+                generics.as_syntax_node().get_text_without_trivia(&db)
+            )),
+        );
+    }
+
+    // A model containing itself would have an infinitely recursive layout.
+    if let Some(member) = struct_ast
+        .members(&db)
+        .elements(&db)
+        .into_iter()
+        .find(|member| refers_to(&db, &member.type_clause(&db).ty(&db), &model_type))
+    {
+        return error_result(
+            input,
+            Diagnostic::error(format!(
+                "The model '{}' cannot contain itself, but its member '{}' is of type '{}'.",
+                model_type,
+                member.name(&db).text(&db),
+                member
+                    .type_clause(&db)
+                    .ty(&db)
+                    .as_syntax_node()
+                    .get_text_without_trivia(&db)
+            )),
+        );
+    }
+
+    let members = struct_ast
+        .members(&db)
+        .elements(&db)
+        .iter()
+        .map(|member| Member {
+            name: member.name(&db).text(&db).to_string(),
+            ty: member
+                .type_clause(&db)
+                .ty(&db)
+                .as_syntax_node()
+                .get_text_without_trivia(&db),
+            key: member.has_attr(&db, KEY_ATTR),
+        })
+        .collect::<Vec<_>>();
+
+    let derives = derive_names(&db, &struct_ast);
+    let is_packed = derives.iter().any(|d| d == INTROSPECT_PACKED_DERIVE);
+
+    let diagnostics = validate(&model_type, &members, &derives);
+    if !diagnostics.is_empty() {
+        return error_result(input, diagnostics);
+    }
+
+    if args.default {
+        let non_default = members
+            .iter()
+            .filter(|m| !m.key && type_names(&m.ty).any(|t| NON_DEFAULT_TYPES.contains(&t)))
+            .map(|m| format!("'{}'", m.name))
+            .collect::<Vec<_>>();
+        if !non_default.is_empty() {
+            return error_result(
+                input,
+                Diagnostic::error(format!(
+                    "The model '{}' derives Default on its value struct, but the members {} have types without a Default implementation.",
+                    model_type,
+                    non_default.join(", ")
+                )),
+            );
+        }
+    }
+
+    if derives
+        .iter()
+        .chain(args.derive.iter())
+        .any(|d| d == CLONE_DERIVE)
+    {
+        let non_clone = members
+            .iter()
+            .filter(|m| type_names(&m.ty).any(|t| NON_CLONE_TYPES.contains(&t)))
+            .map(|m| format!("'{}'", m.name))
+            .collect::<Vec<_>>();
+        if !non_clone.is_empty() {
+            return error_result(
+                input,
+                Diagnostic::error(format!(
+                    "The model '{}' derives Clone, but the members {} have types which can't be cloned.",
+                    model_type,
+                    non_clone.join(", ")
+                )),
+            );
+        }
+    }
+
+    // Packed models are stored in a fixed number of felts, known here when the members are
+    // made of core types only. Keys are not stored, and so are not part of the size.
+    let packed_size = if is_packed {
+        let value_types = struct_ast
+            .members(&db)
+            .elements(&db)
+            .into_iter()
+            .filter(|m| !m.has_attr(&db, KEY_ATTR))
+            .map(|m| {
+                (
+                    m.name(&db).text(&db).to_string(),
+                    m.type_clause(&db).ty(&db),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let dynamic = value_types
+            .iter()
+            .filter(|(_, ty)| is_dynamic(&db, ty))
+            .map(|(name, _)| format!("'{}'", name))
+            .collect::<Vec<_>>();
+        if !dynamic.is_empty() {
+            return error_result(
+                input,
+                Diagnostic::error(format!(
+                    "The model '{}' derives {}, but the members {} have a dynamic size.",
+                    model_type,
+                    INTROSPECT_PACKED_DERIVE,
+                    dynamic.join(", ")
+                )),
+            );
+        }
+
+        value_types
+            .iter()
+            .map(|(_, ty)| fixed_size(&db, ty))
+            .sum::<Option<usize>>()
+    } else {
+        None
+    };
+
+    let (keys, values): (Vec<_>, Vec<_>) = members.iter().partition(|m| m.key);
+
+    let (key_type, keys_to_tuple) = match keys.as_slice() {
+        [key] => (key.ty.clone(), format!("*self.{}", key.name)),
+        keys => (
+            format!(
+                "({})",
+                keys.iter()
+                    .map(|k| k.ty.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format!(
+                "({})",
+                keys.iter()
+                    .map(|k| format!("*self.{}", k.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
+    };
+
+    let members_values = values
+        .iter()
+        .map(|m| format!("pub {}: {},", m.name, m.ty))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Models are introspected, unless they already opted for the packed layout.
+    let mut required_derives = REQUIRED_DERIVES.to_vec();
+    if !is_packed {
+        required_derives.push(INTROSPECT_DERIVE);
+    }
+    required_derives.extend(args.derive.iter().map(String::as_str));
+
+    let model_derives = merge_attributes(&db, struct_ast.attributes(&db), &[], &required_derives);
+
+    let mut value_derives = REQUIRED_DERIVES.map(String::from).to_vec();
+    for derive in derives.into_iter().chain(args.derive.iter().cloned()) {
+        if derive != INTROSPECT_DERIVE
+            && derive != INTROSPECT_PACKED_DERIVE
+            && !value_derives.contains(&derive)
+        {
+            value_derives.push(derive);
+        }
+    }
+    if args.default && !value_derives.iter().any(|d| d == DEFAULT_DERIVE) {
+        value_derives.push(DEFAULT_DERIVE.to_string());
+    }
+
+    // The struct is re-emitted as written, with its attributes replaced by the merged ones.
+    let struct_text = struct_ast.as_syntax_node().get_text(&db);
+    let attributes_text = struct_ast.attributes(&db).as_syntax_node().get_text(&db);
+    let model_struct = format!(
+        "{}\n{}",
+        model_derives,
+        struct_text
+            .strip_prefix(attributes_text.as_str())
+            .unwrap_or(&struct_text)
+    );
+
+    let unique_hash = stable_hash(&format!(
+        "{},{},{}",
+        model_type,
+        is_packed,
+        members
+            .iter()
+            .map(|m| format!("{}:{}:{}", m.name, m.ty, m.key))
+            .collect::<Vec<_>>()
+            .join(",")
+    ));
+
+    // Without the value struct, models are only read and written as a whole.
+    let (model_patch, ensure_values) = if args.no_value_struct {
+        (MODEL_PATCH.to_string(), String::new())
+    } else {
+        (
+            format!("{}\n{}", MODEL_PATCH, MODEL_VALUE_PATCH),
+            "// Outputs ModelValue to allow a simple diff from the ABI compared to the
+        // model to retrieved the keys of a model.
+        #[external(v0)]
+        fn ensure_values(self: @ContractState, value: super::$model_type$Value) {
+            let _value = value;
+        }"
+            .to_string(),
+        )
+    };
+
+    // Legacy selectors only hash the model name, as models used to be namespace-less.
+    let (namespace_hash, selector) = if args.legacy {
+        ("_namespace_hash", format!("selector!(\"{}\")", model_type))
+    } else {
+        (
+            "namespace_hash",
+            format!(
+                "dojo::utils::selector_from_namespace_and_name(namespace_hash, @\"{}\")",
+                model_type
+            ),
+        )
+    };
+
+    let model_code = model_patch
+        .replace("$ensure_values$", &ensure_values)
+        .replace("$selector$", &selector)
+        .replace("$layout_version$", &args.layout_version().to_string())
+        .replace(
+            "$packed_size$",
+            &packed_size
+                .map(|size| {
+                    format!(
+                        "/// Number of felts the packed model is stored in.
+    pub const PACKED_SIZE: usize = {};",
+                        size
+                    )
+                })
+                .unwrap_or_default(),
+        )
+        .replace("$namespace_hash$", namespace_hash)
+        .replace("$model_value_derive_attr_names$", &value_derives.join(", "))
+        .replace("$members_values$", &members_values)
+        .replace("$key_type$", &key_type)
+        .replace("$keys_to_tuple$", &keys_to_tuple)
+        .replace("$serialized_keys$", &serialize_members(&keys))
+        .replace(
+            "$key_params$",
+            &keys
+                .iter()
+                .map(|k| format!("{}: {}", k.name, k.ty))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+        .replace(
+            "$serialized_key_params$",
+            &keys
+                .iter()
+                .map(|k| {
+                    format!(
+                        "core::serde::Serde::serialize(@{}, ref serialized);",
+                        k.name
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+        .replace("$serialized_values$", &serialize_members(&values))
+        .replace("$field_accessors$", &field_accessors(&values))
+        .replace("$unique_hash$", &unique_hash)
+        .replace("$model_type$", &model_type);
+
+    let result = ProcMacroResult::new(TokenStream::new(format!(
+        "{}\n{}",
+        model_struct, model_code
+    )));
+
+    // Most user types do implement the traits, so the hint is opt-in.
+    let hint = args
+        .nested_hints
+        .then(|| nested_types_hint(&model_type, &values, args.default))
+        .flatten();
+    let result = match hint {
+        Some(hint) => result.with_diagnostics(vec![hint].into()),
+        None => result,
+    };
+
+    #[cfg(feature = "emit-metadata")]
+    let result = result.with_aux_data(crate::manifest::model_aux_data(&ModelExpansion {
+        name: model_type.clone(),
+        legacy: args.legacy,
+        selector,
+        layout_version: args.layout_version(),
+    }));
+
+    result
+}
+
+fn validate(model_type: &str, members: &[Member], derives: &[String]) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    if !members.iter().any(|m| m.key) {
+        diagnostics.push(Diagnostic::error(format!(
+            "The model '{}' must define at least one #[key] member.",
+            model_type
+        )));
+    }
+
+    if members.iter().all(|m| m.key) {
+        diagnostics.push(Diagnostic::error(format!(
+            "The model '{}' must define at least one member that is not a key.",
+            model_type
+        )));
+    }
+
+    for key in members.iter().filter(|m| m.key) {
+        let ty = key.ty.rsplit("::").next().unwrap_or(&key.ty);
+        if !KEY_TYPES.contains(&ty) {
+            diagnostics.push(Diagnostic::error(format!(
+                "The key member '{}' of model '{}' has the unsupported type '{}', keys must be one of: {}.",
+                key.name,
+                model_type,
+                key.ty,
+                KEY_TYPES.join(", ")
+            )));
+        }
+    }
+
+    if let Some(first_value) = members.iter().position(|m| !m.key) {
+        if members[first_value..].iter().any(|m| m.key) {
+            diagnostics.push(Diagnostic::error(format!(
+                "The key members of model '{}' must be defined before its non-key members.",
+                model_type
+            )));
+        }
+    }
+
+    for key in members
+        .iter()
+        .filter(|m| m.key && RESERVED_KEY_NAMES.contains(&m.name.as_str()))
+    {
+        diagnostics.push(Diagnostic::error(format!(
+            "The key member '{}' of model '{}' collides with a name used by the generated code, please rename it.",
+            key.name, model_type
+        )));
+    }
+
+    // Member accessors are named after the members, with a `_from_id` variant.
+    for value in members.iter().filter(|m| !m.key) {
+        if let Some(other) = members
+            .iter()
+            .find(|m| !m.key && m.name == format!("{}_from_id", value.name))
+        {
+            diagnostics.push(Diagnostic::error(format!(
+                "The members '{}' and '{}' of model '{}' have colliding accessors 'get_{}', please rename one of them.",
+                value.name, other.name, model_type, other.name
+            )));
+        }
+    }
+
+    if derives.iter().any(|d| d == INTROSPECT_DERIVE)
+        && derives.iter().any(|d| d == INTROSPECT_PACKED_DERIVE)
+    {
+        diagnostics.push(Diagnostic::error(format!(
+            "The model '{}' cannot derive both {} and {}.",
+            model_type, INTROSPECT_DERIVE, INTROSPECT_PACKED_DERIVE
+        )));
+    }
+
+    diagnostics
+}
+
+/// Hint on the value members of user types, which must implement `Introspect`, and `Default`
+/// if derived, for the model to compile. Whether they do can't be known by the macro, so the
+/// hint is only a warning.
+fn nested_types_hint(model_type: &str, values: &[&Member], default: bool) -> Option<Diagnostic> {
+    let nested = values
+        .iter()
+        .filter(|m| {
+            m.ty.chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+                && m.ty != "ByteArray"
+                && primitive_size(&m.ty).is_none()
+        })
+        .map(|m| format!("'{}'", m.ty))
+        .collect::<Vec<_>>();
+
+    let (traits, derives) = if default {
+        ("Introspect and Default", "Introspect, Default")
+    } else {
+        ("Introspect", "Introspect")
+    };
+
+    (!nested.is_empty()).then(|| {
+        Diagnostic::warn(format!(
+            "The model '{}' has members of types {}, which must implement {}: make sure they are declared with #[derive({})].",
+            model_type,
+            nested.join(", "),
+            traits,
+            derives
+        ))
+    })
+}
+
+/// Names of all the traits derived on the struct, in declaration order.
+fn derive_names(db: &dyn SyntaxGroup, struct_ast: &ast::ItemStruct) -> Vec<String> {
+    struct_ast
+        .attributes(db)
+        .elements(db)
+        .into_iter()
+        .map(|attr| attr.structurize(db))
+        .filter(|attr| attr.id == "derive")
+        .flat_map(|attr| attr.args.into_iter().map(|arg| arg.text(db)))
+        .collect()
+}
+
+/// Names of the types a member type is made of, without their module path, such as
+/// `Array`, `NonZero` and `u8` for `Array<core::zeroable::NonZero<u8>>`.
+fn type_names(ty: &str) -> impl Iterator<Item = &str> {
+    ty.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+        .filter_map(|path| path.rsplit("::").next())
+        .filter(|name| !name.is_empty())
+}
+
+/// Getters and setters of each value member, addressed by its member selector.
+fn field_accessors(values: &[&Member]) -> String {
+    values
+        .iter()
+        .map(|m| {
+            MODEL_FIELD_STORE_PATCH
+                .replace("$field_name$", &m.name)
+                .replace("$field_type$", &m.ty)
+                .replace("$field_selector$", &format!("selector!(\"{}\")", m.name))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Serializes the given members of `self`, in order, into the `serialized` array.
+fn serialize_members(members: &[&Member]) -> String {
+    members
+        .iter()
+        .map(|m| {
+            format!(
+                "core::serde::Serde::serialize(self.{}, ref serialized);",
+                m.name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{messages, parses};
+
+    /// Expanded code and diagnostic messages of a model.
+    fn expansion(attr: &str, input: &str) -> (String, Vec<String>) {
+        let result = match ModelArgs::parse(attr) {
+            Ok(args) => expand(&args, input),
+            Err(diagnostics) => error_result(input, diagnostics),
+        };
+        (result.token_stream.to_string(), messages(&result))
+    }
+
+    #[test]
+    fn single_key_is_a_scalar() {
+        let (code, diagnostics) = expansion(
+            "",
+            "struct Position { #[key] player: ContractAddress, x: u32 }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("type PositionKeyType = ContractAddress;"));
+        assert!(code.contains("*self.player\n"));
+    }
+
+    #[test]
+    fn composite_keys_are_a_tuple() {
+        let (code, diagnostics) = expansion(
+            "",
+            "struct Tile { #[key] x: u32, #[key] y: u32, owner: ContractAddress }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("type TileKeyType = (u32, u32);"));
+        assert!(code.contains("(*self.x, *self.y)"));
+    }
+
+    #[test]
+    fn value_struct_is_skipped_under_the_flag() {
+        let input = "struct Position { #[key] player: ContractAddress, x: u32 }";
+        let (default, _) = expansion("", input);
+        let (skipped, diagnostics) = expansion("no_value_struct", input);
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(default.contains("pub struct PositionValue {"));
+        assert!(!skipped.contains("PositionValue"));
+    }
+
+    #[test]
+    fn key_types_are_restricted() {
+        let (_, diagnostics) = expansion("", "struct Player { #[key] name: ByteArray, level: u8 }");
+        assert_eq!(
+            diagnostics,
+            [format!(
+                "The key member 'name' of model 'Player' has the unsupported type 'ByteArray', keys must be one of: {}.",
+                KEY_TYPES.join(", ")
+            )]
+        );
+
+        let (_, diagnostics) = expansion("", "struct Player { #[key] id: felt252, level: u8 }");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn legacy_selector_ignores_the_namespace() {
+        let input = "struct Position { #[key] player: ContractAddress, x: u32 }";
+        let (current, _) = expansion("", input);
+        let (legacy, diagnostics) = expansion("legacy", input);
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(current.contains(
+            "pub fn selector(namespace_hash: felt252) -> felt252 {
+        dojo::utils::selector_from_namespace_and_name(namespace_hash, @\"Position\")"
+        ));
+        assert!(legacy.contains(
+            "pub fn selector(_namespace_hash: felt252) -> felt252 {
+        selector!(\"Position\")"
+        ));
+    }
+
+    #[test]
+    fn value_members_get_accessors() {
+        let (code, diagnostics) = expansion(
+            "",
+            "struct Position { #[key] player: ContractAddress, x: u32, y: u64 }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        for (field, ty) in [("x", "u32"), ("y", "u64")] {
+            assert!(code.contains(&format!(
+                "fn get_{}(self: @S, key: PositionKeyType) -> {} {{",
+                field, ty
+            )));
+            assert!(code.contains(&format!(
+                "fn update_{}_from_id(ref self: S, entity_id: felt252, value: {}) {{",
+                field, ty
+            )));
+            assert!(code.contains(&format!("selector!(\"{}\")", field)));
+        }
+        assert!(!code.contains("fn get_player("));
+        assert!(parses(&code));
+    }
+
+    #[test]
+    fn nested_types_hint_is_opt_in() {
+        let input = "struct Position { #[key] player: ContractAddress, vec: Vec2, x: u32 }";
+        let (_, silent) = expansion("", input);
+        let (_, hinted) = expansion("nested_hints", input);
+
+        assert!(silent.is_empty(), "{:?}", silent);
+        assert_eq!(
+            hinted,
+            ["The model 'Position' has members of types 'Vec2', which must implement Introspect: make sure they are declared with #[derive(Introspect)]."]
+        );
+    }
+
+    #[test]
+    fn keys_are_serialized_in_source_order() {
+        let (code, diagnostics) = expansion(
+            "",
+            "struct Tile { #[key] y: u32, #[key] x: u32, owner: ContractAddress }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("type TileKeyType = (u32, u32);"));
+        assert!(code.contains(
+            "core::serde::Serde::serialize(self.y, ref serialized);\ncore::serde::Serde::serialize(self.x, ref serialized);"
+        ));
+        assert!(code.contains("(*self.y, *self.x)"));
+    }
+
+    #[test]
+    fn requested_derives_are_merged_on_both_structs() {
+        let (code, diagnostics) = expansion(
+            "derive(PartialEq)",
+            "#[derive(Drop, Serde)]\nstruct Position { #[key] player: ContractAddress, x: u32 }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let derive_of = |item: &str| {
+            let at = code.find(item).unwrap();
+            code[..at]
+                .rsplit("#[derive(")
+                .next()
+                .unwrap()
+                .split(")]")
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        assert!(derive_of("struct Position {").contains("PartialEq"));
+        assert!(derive_of("struct PositionValue {").contains("PartialEq"));
+        assert_eq!(derive_of("struct Position {").matches("Drop").count(), 1);
+    }
+
+    #[test]
+    fn layout_version_constant_is_emitted() {
+        let input = "struct Position { #[key] player: ContractAddress, x: u32 }";
+
+        let (code, diagnostics) = expansion("layout_version: 1", input);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("pub const LAYOUT_VERSION: u64 = 1;"));
+
+        let (code, _) = expansion("", input);
+        assert!(code.contains("pub const LAYOUT_VERSION: u64 = 1;"));
+
+        let (_, diagnostics) = expansion("layout_version: 3", input);
+        assert_eq!(
+            diagnostics,
+            ["Unsupported layout version '3', supported versions are: 1."]
+        );
+    }
+
+    #[test]
+    fn entity_pointer_is_built_from_the_keys() {
+        let (code, diagnostics) = expansion(
+            "",
+            "struct Tile { #[key] x: u32, #[key] y: u32, owner: ContractAddress }",
+        );
+
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("pub struct TilePtr {\n    pub id: felt252,\n}"));
+        assert!(code.contains(
+            "fn from_keys(x: u32, y: u32) -> TilePtr {
+        let mut serialized = core::array::ArrayTrait::new();
+        core::serde::Serde::serialize(@x, ref serialized);
+core::serde::Serde::serialize(@y, ref serialized);"
+        ));
+    }
+
+    #[test]
+    fn keys_colliding_with_generated_names_are_rejected() {
+        let (_, diagnostics) = expansion("", "struct Position { #[key] serialized: u32, x: u32 }");
+        assert_eq!(
+            diagnostics,
+            ["The key member 'serialized' of model 'Position' collides with a name used by the generated code, please rename it."]
+        );
+
+        // Generated methods are called through their trait, so members may share their names.
+        let (_, diagnostics) = expansion("", "struct Position { #[key] keys: u32, values: u32 }");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn colliding_member_accessors_are_rejected() {
+        let (_, diagnostics) = expansion(
+            "",
+            "struct Position { #[key] player: ContractAddress, x: u32, x_from_id: u32 }",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["The members 'x' and 'x_from_id' of model 'Position' have colliding accessors 'get_x_from_id', please rename one of them."]
+        );
+    }
+
+    #[test]
+    fn default_is_derived_on_the_value_struct() {
+        let input = "struct Position { #[key] player: ContractAddress, x: u32 }";
+        let derive_of = |code: &str, item: &str| {
+            let at = code.find(item).unwrap();
+            code[..at]
+                .rsplit("#[derive(")
+                .next()
+                .unwrap()
+                .split(")]")
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        let (code, diagnostics) = expansion("default", input);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(derive_of(&code, "struct PositionValue {").contains("Default"));
+        assert!(!derive_of(&code, "struct Position {").contains("Default"));
+
+        let (_, diagnostics) = expansion(
+            "default",
+            "struct Position { #[key] player: ContractAddress, x: NonZero<u32>, y: core::zeroable::NonZero<u8> }",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The model 'Position' derives Default on its value struct, but the members 'x', 'y' have types without a Default implementation."]
+        );
+
+        let (_, diagnostics) = expansion(
+            "default",
+            "struct Position { #[key] player: ContractAddress, x: MyNonZeroCounter, y: NonZeroable }",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let (_, diagnostics) = expansion("default, no_value_struct", input);
+        assert_eq!(
+            diagnostics,
+            ["The 'default' argument derives Default on the value struct, which is skipped by 'no_value_struct'."]
+        );
+    }
+
+    #[test]
+    fn clone_is_derived_when_requested() {
+        let (code, diagnostics) = expansion(
+            "derive(Clone)",
+            "struct Position { #[key] player: ContractAddress, x: u32 }",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        for item in ["struct Position {", "struct PositionValue {"] {
+            let derive = code[..code.find(item).unwrap()]
+                .rsplit("#[derive(")
+                .next()
+                .unwrap();
+            assert!(
+                derive.split(")]").next().unwrap().contains("Clone"),
+                "{}",
+                item
+            );
+        }
+
+        let (_, diagnostics) = expansion(
+            "derive(Clone)",
+            "struct Position { #[key] player: ContractAddress, x: Felt252Dict<u32>, y: core::dict::Felt252DictEntry<u8> }",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The model 'Position' derives Clone, but the members 'x', 'y' have types which can't be cloned."]
+        );
+
+        let (_, diagnostics) = expansion(
+            "derive(Clone)",
+            "struct Position { #[key] player: ContractAddress, x: MyFelt252DictCache, y: Felt252DictLike<u8> }",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn packed_size_is_the_sum_of_the_value_sizes() {
+        let (code, diagnostics) = expansion(
+            "",
+            "#[derive(IntrospectPacked)]
+struct Position { #[key] player: ContractAddress, x: u8, y: u256, z: felt252 }",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert!(code.contains("pub const PACKED_SIZE: usize = 4;"));
+
+        let (_, diagnostics) = expansion(
+            "",
+            "#[derive(IntrospectPacked)]
+struct Position { #[key] player: ContractAddress, name: ByteArray }",
+        );
+        assert_eq!(
+            diagnostics,
+            ["The model 'Position' derives IntrospectPacked, but the members 'name' have a dynamic size."]
+        );
+    }
+
+    #[test]
+    fn generic_models_are_rejected() {
+        let input = "struct M<T> { #[key] id: T, x: u32 }";
+        let (code, diagnostics) = expansion("", input);
+        assert_eq!(
+            diagnostics,
+            ["The model 'M' cannot be generic, but declares '<T>'."]
+        );
+        assert_eq!(code, input);
+    }
+
+    #[test]
+    fn self_referential_models_are_rejected() {
+        let (_, diagnostics) =
+            expansion("", "struct Node { #[key] id: u32, children: Array<Node> }");
+        assert_eq!(
+            diagnostics,
+            ["The model 'Node' cannot contain itself, but its member 'children' is of type 'Array<Node>'."]
+        );
+    }
+}
@@ -1,14 +1,7 @@
-#[abi(per_item)]
-#[generate_trait]
-pub impl IDojoInitImpl of IDojoInit {
-    #[external(v0)]
-    fn $init_name$(self: @ContractState) {
-        if starknet::get_caller_address() != self.world_provider.world_dispatcher().contract_address {
-            core::panics::panic_with_byte_array(
-                @format!("Only the world can init contract `{}`, but caller is `{:?}`",
-                self.dojo_name(),
-                starknet::get_caller_address(),
-            ));
-        }
+$init_header$
+    $init_fn_attrs$
+    fn $init_name$(self: @$state_type$) {
+        $init_caller_check$
+        $init_assert$
     }
 }
@@ -28,6 +28,12 @@ pub impl $type_name$DojoEventImpl of dojo::event::Event<$type_name$> {
         }
     }
 
+    // Historical events are all kept by indexers, others only as the latest per keys.
+    #[inline(always)]
+    fn historical() -> bool {
+        $historical$
+    }
+
     #[inline(always)]
     fn keys(self: @$type_name$) -> Span<felt252> {
         let mut serialized = core::array::ArrayTrait::new();
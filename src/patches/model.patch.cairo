@@ -1,8 +1,3 @@
-#[derive($model_value_derive_attr_names$)]
-pub struct $model_type$Value {
-    $members_values$
-} 
-
 type $model_type$KeyType = $key_type$;
 
 pub impl $model_type$KeyParser of dojo::model::model::KeyParser<$model_type$, $model_type$KeyType>{
@@ -12,12 +7,36 @@ pub impl $model_type$KeyParser of dojo::model::model::KeyParser<$model_type$, $m
     }
 }
 
-impl $model_type$ModelValueKey of dojo::model::model_value::ModelValueKey<$model_type$Value, $model_type$KeyType> {
+// Pointer to an entity of the model, addressed by its keys without being loaded.
+#[derive(Copy, Drop, Serde, Debug, PartialEq)]
+pub struct $model_type$Ptr {
+    pub id: felt252,
+}
+
+#[generate_trait]
+pub impl $model_type$PtrImpl of $model_type$PtrTrait {
+    fn from_keys($key_params$) -> $model_type$Ptr {
+        let mut serialized = core::array::ArrayTrait::new();
+        $serialized_key_params$
+        $model_type$Ptr { id: core::poseidon::poseidon_hash_span(core::array::ArrayTrait::span(@serialized)) }
+    }
 }
 
 // Impl to get the static definition of a model
 pub mod m_$model_type$_definition {
     use super::$model_type$;
+
+    /// Version of the storage layout the model is stored with.
+    pub const LAYOUT_VERSION: u64 = $layout_version$;
+
+    $packed_size$
+
+    /// Selector of the model in the given namespace.
+    #[inline(always)]
+    pub fn selector($namespace_hash$: felt252) -> felt252 {
+        $selector$
+    }
+
     pub impl $model_type$DefinitionImpl<T> of dojo::model::ModelDefinition<T>{
         #[inline(always)]
         fn name() -> ByteArray {
@@ -47,7 +66,6 @@ pub mod m_$model_type$_definition {
 }
 
 pub impl $model_type$Definition = m_$model_type$_definition::$model_type$DefinitionImpl<$model_type$>;
-pub impl $model_type$ModelValueDefinition = m_$model_type$_definition::$model_type$DefinitionImpl<$model_type$Value>;
 
 pub impl $model_type$ModelParser of dojo::model::model::ModelParser<$model_type$>{
     fn serialize_keys(self: @$model_type$) -> Span<felt252> {
@@ -62,21 +80,17 @@ pub impl $model_type$ModelParser of dojo::model::model::ModelParser<$model_type$
     }
 } 
 
-pub impl $model_type$ModelValueParser of dojo::model::model_value::ModelValueParser<$model_type$Value>{
-    fn serialize_values(self: @$model_type$Value) -> Span<felt252> {
-        let mut serialized = core::array::ArrayTrait::new();
-        $serialized_values$
-        core::array::ArrayTrait::span(@serialized)
-    }
-}
-
 pub impl $model_type$ModelImpl = dojo::model::model::ModelImpl<$model_type$>;
-pub impl $model_type$ModelValueImpl = dojo::model::model_value::ModelValueImpl<$model_type$Value>;
+
+// Read and write a single member of the model, by keys or by entity id.
+#[generate_trait]
+pub impl $model_type$MembersStoreImpl<S, +dojo::model::ModelStorage<S, $model_type$>, +Drop<S>> of $model_type$MembersStore<S> {
+$field_accessors$
+}
 
 #[starknet::contract]
 pub mod m_$model_type$ {
     use super::$model_type$;
-    use super::$model_type$Value;
 
     #[storage]
     struct Storage {}
@@ -100,12 +114,7 @@ pub mod m_$model_type$ {
             let _model = model;
         }
 
-        // Outputs ModelValue to allow a simple diff from the ABI compared to the
-        // model to retrieved the keys of a model.
-        #[external(v0)]
-        fn ensure_values(self: @ContractState, value: $model_type$Value) {
-            let _value = value;
-        }
+        $ensure_values$
 
         // Ensures the generated contract has a unique classhash, using
         // a hardcoded hash computed on model and member names.
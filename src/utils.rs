@@ -0,0 +1,275 @@
+use cairo_lang_macro::{Diagnostics, ProcMacroResult, TokenStream};
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::attribute::structured::AttributeStructurize;
+use cairo_lang_syntax::node::ast;
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::{SyntaxNode, TypedSyntaxNode};
+
+/// Builds an error result which still carries the original item, so the user's source
+/// isn't blanked out in the editor while the diagnostics are fixed.
+pub(crate) fn error_result(input: &str, diagnostics: impl Into<Diagnostics>) -> ProcMacroResult {
+    ProcMacroResult::new(TokenStream::new(input.to_string())).with_diagnostics(diagnostics.into())
+}
+
+/// Dojo tag of a resource, its name qualified by its namespace if any, as in `ns-name`.
+pub(crate) fn dojo_tag(namespace: Option<&str>, name: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{}-{}", namespace, name),
+        None => name.to_string(),
+    }
+}
+
+/// Merges the attributes the user put on an item with the ones required by the macro.
+///
+/// Identical attributes are only emitted once, and the arguments of every `derive` are
+/// combined into a single deduplicated `derive` attribute.
+pub(crate) fn merge_attributes(
+    db: &dyn SyntaxGroup,
+    attributes: ast::AttributeList,
+    required: &[&str],
+    required_derives: &[&str],
+) -> String {
+    let mut attrs = required
+        .iter()
+        .map(|attr| format!("#[{}]", attr))
+        .collect::<Vec<_>>();
+    let mut derives = required_derives
+        .iter()
+        .map(|derive| derive.to_string())
+        .collect::<Vec<_>>();
+
+    for attr_ast in attributes.elements(db) {
+        let text = attr_ast.as_syntax_node().get_text_without_trivia(db);
+        let attr = attr_ast.structurize(db);
+
+        if attr.id == "derive" {
+            for arg in attr.args {
+                let derive = arg.text(db);
+                if !derives.contains(&derive) {
+                    derives.push(derive);
+                }
+            }
+        } else if !attrs.contains(&text) {
+            attrs.push(text);
+        }
+    }
+
+    if !derives.is_empty() {
+        attrs.push(format!("#[derive({})]", derives.join(", ")));
+    }
+
+    attrs.join("\n")
+}
+
+/// Texts of the elements of a separated list, each followed by its separator.
+///
+/// The trivia around the separator is kept, so trailing comments as in `A, // note` survive
+/// the elements being emitted apart. The last element gets a comma if it had none, put
+/// before its trailing trivia.
+pub(crate) fn with_separators(
+    db: &dyn SyntaxGroup,
+    list: &SyntaxNode,
+) -> Vec<(SyntaxNode, String)> {
+    db.get_children(list.clone())
+        .chunks(2)
+        .map(|pair| match pair {
+            [element] => {
+                let text = element.get_text(db);
+                let end = element.span_without_trivia(db).to_str_range().end
+                    - element.span(db).to_str_range().start;
+                let (element_text, trivia) = text.split_at(end);
+                let separated = format!("{},{}", element_text, trivia);
+                let newline = if separated.ends_with('\n') { "" } else { "\n" };
+                (element.clone(), format!("{}{}", separated, newline))
+            }
+            _ => (
+                pair[0].clone(),
+                pair.iter().map(|node| node.get_text(db)).collect(),
+            ),
+        })
+        .collect()
+}
+
+/// Maximum length of a Cairo short string, which names are often converted to.
+pub(crate) const SHORT_STRING_MAX_LEN: usize = 31;
+
+pub(crate) fn is_name_valid(name: &str) -> bool {
+    name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Hashes `text` into a felt252 literal which is stable across builds and toolchains,
+/// used to give generated contracts a unique class hash.
+pub(crate) fn stable_hash(text: &str) -> String {
+    // 64-bit FNV-1a, which always fits in a felt252.
+    let hash = text.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("0x{:x}", hash)
+}
+
+/// Whether `code` parses as a Cairo file without any error.
+pub(crate) fn parses(code: &str) -> bool {
+    SimpleParserDatabase::default().parse_virtual(code).is_ok()
+}
+
+/// Converts a `PascalCase` or `camelCase` name to `snake_case`.
+pub(crate) fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut prev: Option<char> = None;
+
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if prev.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit()) {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+        prev = Some(c);
+    }
+
+    snake
+}
+
+/// Whether the braces of `code` are balanced, ignoring the ones in literals and comments.
+pub(crate) fn braces_balanced(code: &str) -> bool {
+    let mut depth = 0usize;
+    let mut chars = code.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => depth += 1,
+            '}' => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return false,
+            },
+            '"' | '\'' => {
+                while let Some(l) = chars.next() {
+                    if l == '\\' {
+                        chars.next();
+                    } else if l == c {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for l in chars.by_ref() {
+                    if l == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    depth == 0
+}
+
+/// Re-indents `code` with `width` spaces by nesting depth of its brackets.
+///
+/// Lines starting inside a string literal are kept as is, so literals are never altered.
+pub(crate) fn reindent(code: &str, width: usize) -> String {
+    let mut depth = 0usize;
+    let mut in_string = None;
+    let mut lines = vec![];
+
+    for line in code.lines() {
+        let starts_in_string = in_string.is_some();
+        let trimmed = line.trim_start();
+        let leading_closers = trimmed
+            .chars()
+            .take_while(|c| matches!(c, '}' | ')' | ']'))
+            .count();
+        let level = depth.saturating_sub(leading_closers);
+
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (in_string, c) {
+                (Some(_), '\\') => {
+                    chars.next();
+                }
+                (Some(quote), c) if c == quote => in_string = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => in_string = Some(c),
+                (None, '/') if chars.peek() == Some(&'/') => break,
+                (None, '{' | '(' | '[') => depth += 1,
+                (None, '}' | ')' | ']') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        lines.push(if starts_in_string {
+            line.to_string()
+        } else if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("{}{}", " ".repeat(level * width), trimmed)
+        });
+    }
+
+    lines.join("\n")
+}
+
+/// `///` doc comments written before the given node, one per line.
+pub(crate) fn doc_comments(db: &dyn SyntaxGroup, node: &SyntaxNode) -> String {
+    let text = node.get_text(db);
+    let code = node.clone().get_text_without_trivia(db);
+    let leading = text.find(&code).map_or("", |start| &text[..start]);
+
+    leading
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("///"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Messages of the diagnostics of a result, in order.
+#[cfg(test)]
+pub(crate) fn messages(result: &ProcMacroResult) -> Vec<String> {
+    result
+        .diagnostics
+        .clone()
+        .into_iter()
+        .map(|d| d.message)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_is_reindented_by_depth() {
+        let code =
+            "mod m {\n        fn f(\n    a: u32,\n) {\n  let s = \"{\";\n            } // }\n\n}";
+
+        assert_eq!(
+            reindent(code, 2),
+            "mod m {\n  fn f(\n    a: u32,\n  ) {\n    let s = \"{\";\n  } // }\n\n}"
+        );
+    }
+
+    #[test]
+    fn unbalanced_items_are_detected() {
+        assert!(braces_balanced("fn f() { let x = S { a: 1 }; }"));
+        assert!(!braces_balanced("fn f() { let x = S { a: 1 };"));
+        assert!(!braces_balanced("fn f() } {"));
+    }
+
+    #[test]
+    fn braces_in_literals_and_comments_are_ignored() {
+        assert!(braces_balanced("fn f() { let s = \"{\"; }"));
+        assert!(braces_balanced("fn f() { let s = '}'; }"));
+        assert!(braces_balanced("fn f() { // closes }\n}"));
+        assert!(braces_balanced("fn f() { let s = \"\\\"{\"; }"));
+    }
+
+    #[test]
+    fn dojo_tags_join_namespace_and_name() {
+        assert_eq!(dojo_tag(Some("game"), "Position"), "game-Position");
+        assert_eq!(dojo_tag(None, "Position"), "Position");
+    }
+}